@@ -1,72 +1,276 @@
-use proc_macro2::{Ident, Span, TokenStream};
-use quote::quote;
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
 
-fn variant_matcher(variant: &synstructure::VariantInfo) -> TokenStream {
-    let mut variant = variant.clone();
-    variant.binding_name(|_, i| Ident::new(&format!("__other_{}", i), Span::call_site()));
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Interpolated with perspective correction
+    Smooth,
+    /// Interpolated linearly in screen space
+    NoPerspective,
+    /// Taken from the first vertex
+    Flat,
+    /// Set to `Default::default()`
+    Skip,
+}
+
+fn field_mode(field: &syn::Field) -> syn::Result<Mode> {
+    let mut mode = Mode::Smooth;
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("interpolate"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let new_mode = if meta.path.is_ident("flat") {
+                Mode::Flat
+            } else if meta.path.is_ident("noperspective") {
+                Mode::NoPerspective
+            } else if meta.path.is_ident("skip") {
+                Mode::Skip
+            } else {
+                return Err(meta.error("expected `flat`, `noperspective` or `skip`"));
+            };
 
-    for binding in variant.bindings_mut() {
-        binding.style = synstructure::BindStyle::Move;
+            if mode != Mode::Smooth {
+                return Err(meta.error("only one interpolation mode can be used"));
+            }
+            mode = new_mode;
+            Ok(())
+        })?;
     }
 
-    variant.pat()
+    Ok(mode)
 }
 
-fn derive_interpolate(mut s: synstructure::Structure) -> TokenStream {
-    for variant in s.variants_mut() {
-        for binding in variant.bindings_mut() {
-            binding.style = synstructure::BindStyle::Move;
-        }
+/// Attributes on the type itself
+#[derive(Default)]
+struct Container {
+    /// `#[interpolate(scalar = ...)]`, the scalar type of the fields, which
+    /// the renderer's weights are converted to
+    scalar: Option<syn::Type>,
+    /// `#[interpolate(mismatched = flat)]` on enums, which takes the first
+    /// vertex when vertices have different variants
+    mismatched_flat: bool,
+}
+
+fn container(ast: &syn::DeriveInput) -> syn::Result<Container> {
+    let mut container = Container::default();
+
+    for attr in ast
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("interpolate"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("scalar") {
+                container.scalar = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("mismatched") {
+                let value: syn::Ident = meta.value()?.parse()?;
+                if value != "flat" {
+                    return Err(syn::Error::new_spanned(value, "expected `flat`"));
+                }
+                if let syn::Data::Struct(_) = ast.data {
+                    return Err(meta.error("`mismatched` only applies to enums"));
+                }
+                container.mismatched_flat = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `scalar = <type>` or `mismatched = flat`"))
+            }
+        })?;
     }
 
-    let add_body = s.each_variant(|variant| {
-        let pat = variant_matcher(&variant);
-        let construct = variant.construct(|_, i| {
-            let self_binding = Ident::new(&format!("__binding_{}", i), Span::call_site());
-            let other_binding = Ident::new(&format!("__other_{}", i), Span::call_site());
-            quote! {
-                #self_binding + #other_binding
+    Ok(container)
+}
+
+/// Interpolates a field of type `ty` between `values`. Arrays are
+/// interpolated element by element, everything else has to implement
+/// `Interpolate3` itself, which is added to `bounds`.
+fn interpolate_field(
+    ty: &syn::Type,
+    space: &TokenStream,
+    weights: TokenStream,
+    values: [TokenStream; 3],
+    bounds: &mut Vec<syn::WherePredicate>,
+) -> TokenStream {
+    match ty {
+        syn::Type::Array(array) => {
+            let [a, b, c] = values;
+            let element = interpolate_field(
+                &array.elem,
+                space,
+                weights,
+                [quote!(__a), quote!(__b), quote!(__c)],
+                bounds,
+            );
+            quote! {{
+                let mut __iter = ::std::iter::IntoIterator::into_iter(#a)
+                    .zip(::std::iter::IntoIterator::into_iter(#b))
+                    .zip(::std::iter::IntoIterator::into_iter(#c));
+                ::std::array::from_fn(|_| {
+                    let ((__a, __b), __c) = __iter.next().unwrap();
+                    #element
+                })
+            }}
+        }
+        syn::Type::Paren(paren) => interpolate_field(&paren.elem, space, weights, values, bounds),
+        syn::Type::Group(group) => interpolate_field(&group.elem, space, weights, values, bounds),
+        _ => {
+            let span = ty.span();
+            bounds.push(syn::parse_quote_spanned! {span=>
+                #ty: ::termishade::Interpolate3<#space>
+            });
+            let [a, b, c] = values;
+            quote_spanned! {span=>
+                <#ty as ::termishade::Interpolate3<#space>>::interpolate_weights(
+                    #weights, [#a, #b, #c]
+                )
             }
+        }
+    }
+}
+
+fn derive_interpolate(mut s: synstructure::Structure) -> TokenStream {
+    let container = match container(s.ast()) {
+        Ok(container) => container,
+        Err(e) => return e.to_compile_error(),
+    };
+
+    // vertices can have different variants, which have nothing to be
+    // interpolated between
+    let variants = s.variants().len();
+    if variants > 1 && !container.mismatched_flat {
+        return syn::Error::new_spanned(
+            &s.ast().ident,
+            "vertices can have different variants of this enum; add \
+             #[interpolate(mismatched = flat)] to take the first vertex when they do",
+        )
+        .to_compile_error();
+    }
+
+    // fields are interpolated in the renderer's space, unless they have a
+    // scalar type of their own that the weights are converted to
+    let space = match &container.scalar {
+        Some(scalar) => quote! { ::termishade::interpolate::ScalarSpace<#scalar> },
+        None => quote! { __V },
+    };
+
+    let mut bounds = Vec::new();
+    let mut arms = Vec::new();
+    for variant in s.variants() {
+        let modes = match variant
+            .ast()
+            .fields
+            .iter()
+            .map(field_mode)
+            .collect::<syn::Result<Vec<_>>>()
+        {
+            Ok(modes) => modes,
+            Err(e) => return e.to_compile_error(),
+        };
+
+        let pats = ["__a", "__b", "__c"].map(|prefix| {
+            let mut variant = variant.clone();
+            variant.binding_name(|_, i| quote::format_ident!("{}_{}", prefix, i));
+            variant.bind_with(|_| synstructure::BindStyle::Move);
+            variant.pat()
         });
 
-        quote! {
-            match other {
-                #pat => #construct,
-                _ => panic!("Variants don't match")
+        let construct = variant.construct(|field, i| {
+            let values = ["__a", "__b", "__c"].map(|prefix| {
+                let binding = quote::format_ident!("{}_{}", prefix, i);
+                quote!(#binding)
+            });
+
+            match modes[i] {
+                Mode::Smooth => interpolate_field(
+                    &field.ty,
+                    &space,
+                    quote!(perspective, linear),
+                    values,
+                    &mut bounds,
+                ),
+                Mode::NoPerspective => interpolate_field(
+                    &field.ty,
+                    &space,
+                    quote!(linear, linear),
+                    values,
+                    &mut bounds,
+                ),
+                Mode::Flat => values[0].clone(),
+                Mode::Skip => {
+                    let ty = &field.ty;
+                    let span = ty.span();
+                    bounds.push(syn::parse_quote_spanned! {span=>
+                        #ty: ::std::default::Default
+                    });
+                    quote_spanned! {span=> <#ty as ::std::default::Default>::default() }
+                }
             }
-        }
-    });
+        });
 
-    let mul_body = s.each_variant(|variant| {
-        variant.construct(|_, i| {
-            let self_binding = Ident::new(&format!("__binding_{}", i), Span::call_site());
+        let [a, b, c] = pats;
+        arms.push(quote! { (#a, #b, #c) => #construct, });
+    }
+    if variants > 1 {
+        arms.push(quote! { (__a, _, _) => __a, });
+    }
+
+    // only interpolated and skipped fields need bounds
+    s.add_bounds(synstructure::AddBounds::None);
+    for bound in bounds {
+        s.add_where_predicate(bound);
+    }
+
+    let real_field = quote! { __V::RealField };
+    let convert = match &container.scalar {
+        Some(scalar) => {
+            s.add_where_predicate(syn::parse_quote! {
+                #scalar: ::termishade::interpolate::SupersetOf<__V::RealField>
+            });
             quote! {
-                #self_binding * coef
+                let perspective: [#scalar; 3] =
+                    ::termishade::interpolate::convert_weights(perspective);
+                let linear: [#scalar; 3] = ::termishade::interpolate::convert_weights(linear);
             }
-        })
-    });
+        }
+        None => quote! {},
+    };
 
     s.gen_impl(quote! {
-        gen impl std::ops::Add<Self> for @Self {
-            type Output = Self;
-
-            fn add(self, other: Self) -> Self {
-                match self {
-                    #add_body
-                }
+        gen impl<__V> ::termishade::Interpolate3<__V> for @Self
+        where
+            __V: ::termishade::interpolate::InnerSpace
+                + ::termishade::interpolate::FiniteDimVectorSpace
+                + Copy,
+            #real_field: From<f32>,
+        {
+            fn to_barycentric(ps: [__V; 3], p: __V) -> [#real_field; 3] {
+                ::termishade::interpolate::barycentric(ps, p)
             }
-        }
 
-        gen impl std::ops::Mul<f32> for @Self {
-            type Output = Self;
+            fn interpolate(ps: [__V; 3], p: __V, v: [Self; 3]) -> Self {
+                let weights = ::termishade::interpolate::barycentric(ps, p);
+                <Self as ::termishade::Interpolate3<__V>>::interpolate_weights(weights, weights, v)
+            }
 
-            fn mul(self, coef: f32) -> Self {
-                match self {
-                    #mul_body
+            #[allow(unused_variables)]
+            fn interpolate_weights(
+                perspective: [#real_field; 3],
+                linear: [#real_field; 3],
+                v: [Self; 3],
+            ) -> Self {
+                #convert
+                let [__a, __b, __c] = v;
+                match (__a, __b, __c) {
+                    #(#arms)*
                 }
             }
         }
     })
 }
 
-synstructure::decl_derive!([Interpolate] => derive_interpolate);
+synstructure::decl_derive!([Interpolate, attributes(interpolate)] => derive_interpolate);
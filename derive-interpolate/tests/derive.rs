@@ -0,0 +1,217 @@
+use derive_interpolate::Interpolate;
+use nalgebra::{Vector2, Vector3};
+use termishade::Interpolate3;
+
+type V = Vector3<f32>;
+
+const PERSPECTIVE: [f32; 3] = [0.5, 0.25, 0.25];
+const LINEAR: [f32; 3] = [0.0, 0.5, 0.5];
+
+fn interpolate<T: Interpolate3<V>>(v: [T; 3]) -> T {
+    T::interpolate_weights(PERSPECTIVE, LINEAR, v)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Interpolate)]
+struct Modes {
+    smooth: f32,
+    #[interpolate(noperspective)]
+    linear: f32,
+    #[interpolate(flat)]
+    id: u32,
+    #[interpolate(skip)]
+    cached: Option<u8>,
+}
+
+#[test]
+fn field_modes() {
+    let vertex = |x: f32, id| Modes {
+        smooth: x,
+        linear: x,
+        id,
+        cached: Some(1),
+    };
+
+    let out = interpolate([vertex(0.0, 1), vertex(4.0, 2), vertex(8.0, 3)]);
+    assert_eq!(
+        out,
+        Modes {
+            smooth: 3.0,
+            linear: 6.0,
+            id: 1,
+            cached: None,
+        }
+    );
+}
+
+#[test]
+fn interpolate_uses_the_same_weights_for_both() {
+    let vertex = |x: f32| Modes {
+        smooth: x,
+        linear: x,
+        id: 0,
+        cached: None,
+    };
+    let ps = [V::zeros(), V::x(), V::y()];
+    let out = Modes::interpolate(
+        ps,
+        Vector3::new(0.5, 0.5, 0.0),
+        [vertex(0.0), vertex(2.0), vertex(4.0)],
+    );
+    assert_eq!(out.smooth, 3.0);
+    assert_eq!(out.linear, 3.0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Interpolate)]
+enum Single {
+    Color { rgb: Vector3<f32> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Interpolate)]
+#[interpolate(mismatched = flat)]
+enum Shading {
+    Textured(Vector2<f32>, #[interpolate(flat)] u16),
+    Solid(f32),
+    None,
+}
+
+#[test]
+fn enums() {
+    let color = |r| Single::Color {
+        rgb: Vector3::new(r, 0.0, 0.0),
+    };
+    assert_eq!(
+        interpolate([color(0.0), color(4.0), color(8.0)]),
+        color(3.0)
+    );
+
+    let textured = |u| Shading::Textured(Vector2::new(u, 1.0), 7);
+    assert_eq!(
+        interpolate([textured(0.0), textured(4.0), textured(8.0)]),
+        textured(3.0)
+    );
+    assert_eq!(
+        interpolate([
+            Shading::Solid(4.0),
+            Shading::Solid(0.0),
+            Shading::Solid(0.0)
+        ]),
+        Shading::Solid(2.0)
+    );
+
+    // different variants take the first vertex
+    assert_eq!(
+        interpolate([Shading::Solid(4.0), textured(0.0), Shading::None]),
+        Shading::Solid(4.0)
+    );
+    assert_eq!(
+        interpolate([Shading::None, Shading::None, Shading::None]),
+        Shading::None
+    );
+}
+
+// only implements `interpolate`, so it gets the default `interpolate_weights`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Manual(f32);
+
+impl Interpolate3<V> for Manual {
+    fn to_barycentric(ps: [V; 3], p: V) -> [f32; 3] {
+        termishade::interpolate::barycentric(ps, p)
+    }
+
+    fn interpolate(ps: [V; 3], p: V, [a, b, c]: [Self; 3]) -> Self {
+        let [wa, wb, wc] = Self::to_barycentric(ps, p);
+        Manual(a.0 * wa + b.0 * wb + c.0 * wc)
+    }
+}
+
+#[test]
+fn default_interpolate_weights() {
+    let out = interpolate([Manual(0.0), Manual(4.0), Manual(8.0)]);
+    assert_eq!(out, Manual(3.0));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Interpolate)]
+struct Tuple(f32, #[interpolate(flat)] i32);
+
+#[test]
+fn tuple_structs() {
+    let out = interpolate([Tuple(0.0, -1), Tuple(4.0, 2), Tuple(8.0, 3)]);
+    assert_eq!(out, Tuple(3.0, -1));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Interpolate)]
+struct Generic<T, U> {
+    value: T,
+    #[interpolate(flat)]
+    tag: U,
+}
+
+#[test]
+fn generics() {
+    let vertex = |x: f32| Generic {
+        value: Vector2::new(x, -x),
+        tag: "first",
+    };
+    let out = interpolate([vertex(0.0), vertex(4.0), vertex(8.0)]);
+    assert_eq!(out.value, Vector2::new(3.0, -3.0));
+    assert_eq!(out.tag, "first");
+
+    // nested derived types
+    let nested = |x: f32| Generic {
+        value: Tuple(x, 0),
+        tag: (),
+    };
+    assert_eq!(
+        interpolate([nested(0.0), nested(4.0), nested(8.0)]).value,
+        Tuple(3.0, 0)
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Interpolate)]
+struct Arrays {
+    weights: [f32; 3],
+    matrix: [[f32; 2]; 2],
+    #[interpolate(noperspective)]
+    linear: [f32; 2],
+}
+
+#[test]
+fn arrays() {
+    let vertex = |x: f32| Arrays {
+        weights: [x, 2.0 * x, 0.0],
+        matrix: [[x, 1.0], [0.0, -x]],
+        linear: [x, x],
+    };
+    let out = interpolate([vertex(0.0), vertex(4.0), vertex(8.0)]);
+    assert_eq!(
+        out,
+        Arrays {
+            weights: [3.0, 6.0, 0.0],
+            matrix: [[3.0, 1.0], [0.0, -3.0]],
+            linear: [6.0, 6.0],
+        }
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Interpolate)]
+#[interpolate(scalar = f64)]
+struct Precise {
+    position: Vector3<f64>,
+    time: f64,
+}
+
+#[test]
+fn scalar() {
+    let vertex = |x: f64| Precise {
+        position: Vector3::new(x, 0.0, 1e10 + x),
+        time: x,
+    };
+    // interpolated with the renderer's f32 weights, converted to f64
+    let out = interpolate([vertex(0.0), vertex(4.0), vertex(8.0)]);
+    assert_eq!(out.position, Vector3::new(3.0, 0.0, 1e10 + 3.0));
+    assert_eq!(out.time, 3.0);
+
+    // the bounds the renderers have on intermediates
+    fn renderer_accepts<T: Interpolate3<Vector3<f32>> + Copy>() {}
+    renderer_accepts::<Precise>();
+}
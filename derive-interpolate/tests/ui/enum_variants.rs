@@ -0,0 +1,9 @@
+use derive_interpolate::Interpolate;
+
+#[derive(Clone, Copy, Interpolate)]
+enum Material {
+    Solid(f32),
+    Textured(f32, u16),
+}
+
+fn main() {}
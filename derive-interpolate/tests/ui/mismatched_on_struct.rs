@@ -0,0 +1,9 @@
+use derive_interpolate::Interpolate;
+
+#[derive(Interpolate)]
+#[interpolate(mismatched = flat)]
+struct Vertex {
+    color: f32,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use derive_interpolate::Interpolate;
+use termishade::Interpolate3;
+
+#[derive(Interpolate)]
+struct Vertex {
+    color: f32,
+    id: u32,
+}
+
+fn intermediate<T: Interpolate3<nalgebra::Vector3<f32>>>() {}
+
+fn main() {
+    intermediate::<Vertex>();
+}
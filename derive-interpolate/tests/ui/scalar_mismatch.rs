@@ -0,0 +1,10 @@
+use derive_interpolate::Interpolate;
+
+// fields are interpolated with f64 weights
+#[derive(Interpolate)]
+#[interpolate(scalar = f64)]
+struct Vertex {
+    color: nalgebra::Vector3<f32>,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use derive_interpolate::Interpolate;
+
+struct Handle;
+
+#[derive(Interpolate)]
+struct Vertex {
+    color: f32,
+    #[interpolate(skip)]
+    handle: Handle,
+}
+
+fn main() {}
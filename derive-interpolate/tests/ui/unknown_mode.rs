@@ -0,0 +1,9 @@
+use derive_interpolate::Interpolate;
+
+#[derive(Interpolate)]
+struct Vertex {
+    #[interpolate(linear)]
+    color: f32,
+}
+
+fn main() {}
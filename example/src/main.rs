@@ -1,9 +1,10 @@
 extern crate nalgebra_glm as glm;
 use derive_interpolate::Interpolate;
-use termion_target::{Key, TermionTarget};
+use image_target::{AnimationWriter, Recorder};
+use termion_target::{Event, Key, MouseAction, MouseButton, TermionTarget};
 use termishade::{
     blend, next::Extend, rasterizer::TriangleRasterizer, target::RenderTarget, BaseRenderer,
-    ColorDepthRenderer, DrawParams, NalgebraParRenderer, Program,
+    ColorDepthRenderer, DrawParams, NalgebraParRenderer, Program, TemporalAntiAliasing,
 };
 
 struct CubeProgram;
@@ -58,6 +59,10 @@ impl Program for CubeProgram {
     }
 }
 
+fn projection(aspect_ratio: f32) -> glm::Mat4 {
+    glm::perspective::<f32>(aspect_ratio, std::f32::consts::FRAC_PI_3, 0.1, 10.0)
+}
+
 fn main() {
     let input = std::fs::read_to_string(
         std::env::args()
@@ -66,11 +71,14 @@ fn main() {
     )
     .unwrap();
 
-    let multisampling_level = std::env::args()
+    // either a multisampling level or "taa" for temporal anti-aliasing
+    let anti_aliasing = std::env::args()
         .nth(2)
-        .unwrap_or_else(|| String::from("1"))
-        .parse()
-        .expect("invalid multisampling level");
+        .unwrap_or_else(|| String::from("1"));
+    let (multisampling_level, use_taa) = match anti_aliasing.as_str() {
+        "taa" => (1, true),
+        level => (level.parse().expect("invalid multisampling level"), false),
+    };
 
     let cube: obj::Obj = obj::load_obj(std::io::Cursor::new(input)).unwrap();
 
@@ -87,21 +95,57 @@ fn main() {
         .collect::<Vec<_>>();
 
     let mut target = TermionTarget::new().unwrap();
-    let [w, h] = target.size_multisampled(multisampling_level);
-    let mut renderer = ColorDepthRenderer::new(w, h);
+    match std::env::args().nth(3) {
+        Some(path) => {
+            let recording = AnimationWriter::create(path).expect("failed to create recording");
+            let mut recorder = Recorder::new(target, recording);
+            run(
+                &mut recorder,
+                Recorder::get_mut,
+                &cube,
+                multisampling_level,
+                use_taa,
+            );
+            recorder.finish().expect("failed to finish recording");
+        }
+        None => run(&mut target, |t| t, &cube, multisampling_level, use_taa),
+    }
+}
+
+/// Renders until escape is pressed. `terminal` gets the terminal from
+/// `target` to read events.
+fn run<T: RenderTarget<glm::Vec4>>(
+    target: &mut T,
+    terminal: fn(&mut T) -> &mut TermionTarget,
+    cube: &[Vertex],
+    multisampling_level: u8,
+    use_taa: bool,
+) {
+    let [w, h] = target.size();
+    let mut renderer = ColorDepthRenderer::multisampled(w, h, multisampling_level);
+    let mut taa = if use_taa {
+        Some(TemporalAntiAliasing::new(w, h))
+    } else {
+        None
+    };
 
     let mut view = glm::look_at(&glm::vec3(-5.0, 3.0, -4.0), &glm::zero(), &glm::Vec3::y());
 
-    let projection = glm::perspective::<f32>(w as f32 / h as f32, 3.14 / 3.0, 0.1, 10.0);
+    let mut projection = projection(target.aspect_ratio());
+    let mut drag_start = None;
 
     let start = std::time::Instant::now();
     'main: loop {
         let now = std::time::Instant::now();
         let model: glm::Mat4 = glm::rotation((now - start).as_secs_f32(), &glm::Vec3::y());
 
+        let jittered = match &taa {
+            Some(taa) => taa.jitter_projection(&projection),
+            None => projection,
+        };
         let uni = Uniform {
             model,
-            pv: projection * view,
+            pv: jittered * view,
             light: glm::vec3(-1.0, 1.0, 1.0),
         };
 
@@ -114,21 +158,52 @@ fn main() {
                 blender: &blend::Replace,
                 depth_test_enabled: true,
             },
-            &cube,
+            cube,
             &uni,
         );
 
-        target.draw_multisampled(renderer.color_buffer(), multisampling_level);
+        let resolved = renderer.resolve();
+        let frame = match &mut taa {
+            Some(taa) => taa.resolve(resolved),
+            None => resolved,
+        };
+        target.draw(frame);
 
-        while let Some(key) = target.get_key() {
-            match key {
-                Key::Esc | Key::Ctrl('c') => break 'main,
-                Key::Left => {
+        while let Some(event) = terminal(target).get_event() {
+            match event {
+                Event::Key(Key::Esc) | Event::Key(Key::Ctrl('c')) => break 'main,
+                Event::Key(Key::Left) => {
                     view = glm::rotation(-0.01, &glm::Vec3::y()) * view;
                 }
-                Key::Right => {
+                Event::Key(Key::Right) => {
                     view = glm::rotation(0.01, &glm::Vec3::y()) * view;
                 }
+                Event::Mouse(mouse) => match mouse.action {
+                    MouseAction::Press(MouseButton::Left) => drag_start = Some(mouse.pos),
+                    MouseAction::Drag(MouseButton::Left) => {
+                        if let Some([x, _]) = drag_start {
+                            let angle = (mouse.pos[0] - x) * 0.05;
+                            view = glm::rotation(angle, &glm::Vec3::y()) * view;
+                        }
+                        drag_start = Some(mouse.pos);
+                    }
+                    MouseAction::Release => drag_start = None,
+                    MouseAction::ScrollUp => {
+                        view = glm::translation(&glm::vec3(0.0, 0.0, 0.5)) * view;
+                    }
+                    MouseAction::ScrollDown => {
+                        view = glm::translation(&glm::vec3(0.0, 0.0, -0.5)) * view;
+                    }
+                    _ => {}
+                },
+                Event::Resize(_) => {
+                    let [w, h] = target.size();
+                    renderer.resize([w, h]);
+                    if let Some(taa) = &mut taa {
+                        taa.resize([w, h]);
+                    }
+                    projection = self::projection(target.aspect_ratio());
+                }
                 _ => {}
             }
         }
@@ -0,0 +1,498 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use termishade::util::to_rgba8;
+use termishade::RenderTarget;
+
+use crate::format::png_error;
+
+// used for the last frame if there is nothing to measure its duration by
+const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// Guesses the format from a file extension.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes frames with timestamps into an animated GIF or PNG.
+///
+/// The size of the animation is that of the first frame, later frames are
+/// cropped or padded at the bottom right to match it. The file is completed by
+/// `finish`, or on drop, ignoring errors.
+pub struct AnimationWriter<W: Write> {
+    format: AnimationFormat,
+    size: Option<[usize; 2]>,
+    out: Option<W>,
+    gif: Option<gif::Encoder<W>>,
+    // compressed image data and duration in milliseconds of every APNG frame
+    apng_frames: Vec<(Vec<u8>, u16)>,
+    // the last frame waits for the next one to know how long it lasts
+    pending: Option<(Vec<u8>, Duration)>,
+    last_duration: Option<Duration>,
+    rgba: Vec<u8>,
+    finished: bool,
+}
+
+impl AnimationWriter<BufWriter<File>> {
+    /// The format is guessed from the extension of `path`, defaulting to GIF.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let format = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(AnimationFormat::from_extension)
+            .unwrap_or(AnimationFormat::Gif);
+
+        Ok(Self::new(BufWriter::new(File::create(path)?), format))
+    }
+}
+
+impl<W: Write> AnimationWriter<W> {
+    pub fn new(out: W, format: AnimationFormat) -> Self {
+        Self {
+            format,
+            size: None,
+            out: Some(out),
+            gif: None,
+            apng_frames: Vec::new(),
+            pending: None,
+            last_duration: None,
+            rgba: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Adds a frame that is shown from `timestamp`, measured from the start of
+    /// the animation, until the next frame's timestamp.
+    pub fn write_frame(
+        &mut self,
+        buffer: &[nalgebra::Vector4<f32>],
+        width: usize,
+        timestamp: Duration,
+    ) -> io::Result<()> {
+        let [aw, ah] = *self
+            .size
+            .get_or_insert([width, buffer.len() / width.max(1)]);
+
+        to_rgba8(buffer, width, &mut self.rgba);
+        let mut frame = vec![0; aw * ah * 4];
+        for (dst, src) in frame.chunks_mut(aw * 4).zip(self.rgba.chunks(width * 4)) {
+            let len = dst.len().min(src.len());
+            dst[..len].copy_from_slice(&src[..len]);
+        }
+
+        if let Some((prev, prev_timestamp)) = self.pending.take() {
+            let duration = timestamp.checked_sub(prev_timestamp).unwrap_or_default();
+            self.last_duration = Some(duration);
+            self.encode(prev, prev_timestamp, duration)?;
+        }
+        self.pending = Some((frame, timestamp));
+        Ok(())
+    }
+
+    fn encode(
+        &mut self,
+        mut frame: Vec<u8>,
+        timestamp: Duration,
+        duration: Duration,
+    ) -> io::Result<()> {
+        let [width, height] = self.size.unwrap();
+
+        match self.format {
+            AnimationFormat::Gif => {
+                if self.gif.is_none() {
+                    let out = self.out.take().unwrap();
+                    let mut gif = gif::Encoder::new(out, width as u16, height as u16, &[])
+                        .map_err(gif_error)?;
+                    gif.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+                    self.gif = Some(gif);
+                }
+
+                // delays are rounded from timestamps so that errors don't add up
+                let centis = |t: Duration| (t.as_millis() + 5) / 10;
+                let delay = centis(timestamp + duration) - centis(timestamp);
+
+                // gif has no partial transparency
+                for p in frame.chunks_mut(4) {
+                    p[3] = 255;
+                }
+                let mut gif_frame =
+                    gif::Frame::from_rgba_speed(width as u16, height as u16, &mut frame, 10);
+                gif_frame.delay = delay.min(u16::MAX as u128) as u16;
+                self.gif
+                    .as_mut()
+                    .unwrap()
+                    .write_frame(&gif_frame)
+                    .map_err(gif_error)?;
+            }
+            AnimationFormat::Apng => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                for row in frame.chunks(width * 4) {
+                    // no filter
+                    encoder.write_all(&[0])?;
+                    encoder.write_all(row)?;
+                }
+                let millis = duration.as_millis().min(u16::MAX as u128) as u16;
+                self.apng_frames.push((encoder.finish()?, millis));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the remaining frames and returns the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_mut()?;
+        Ok(self.out.take().unwrap())
+    }
+
+    fn finish_mut(&mut self) -> io::Result<()> {
+        self.finished = true;
+
+        if let Some((frame, timestamp)) = self.pending.take() {
+            let duration = self.last_duration.unwrap_or(DEFAULT_FRAME_DURATION);
+            self.encode(frame, timestamp, duration)?;
+        }
+
+        match self.format {
+            AnimationFormat::Gif => {
+                if let Some(gif) = self.gif.take() {
+                    self.out = Some(gif.into_inner()?);
+                }
+            }
+            AnimationFormat::Apng if !self.apng_frames.is_empty() => {
+                let [width, height] = self.size.unwrap();
+                let apng = encode_apng([width as u32, height as u32], &self.apng_frames)
+                    .map_err(png_error)?;
+                self.out.as_mut().unwrap().write_all(&apng)?;
+            }
+            AnimationFormat::Apng => {}
+        }
+
+        self.out.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for AnimationWriter<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finish_mut();
+        }
+    }
+}
+
+// in memory, because png only writes the end of the file when its writer is
+// dropped, ignoring errors
+fn encode_apng(
+    [width, height]: [u32; 2],
+    frames: &[(Vec<u8>, u16)],
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut apng = Vec::new();
+    let mut encoder = png::Encoder::new(&mut apng, width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+
+    let mut actl = Vec::new();
+    actl.extend_from_slice(&(frames.len() as u32).to_be_bytes());
+    // loop forever
+    actl.extend_from_slice(&0u32.to_be_bytes());
+    writer.write_chunk(*b"acTL", &actl)?;
+
+    // frame control and frame data chunks share the sequence numbers
+    let mut sequence = 0u32;
+    let mut chunk = Vec::new();
+    for (i, (data, millis)) in frames.iter().enumerate() {
+        chunk.clear();
+        chunk.extend_from_slice(&sequence.to_be_bytes());
+        chunk.extend_from_slice(&width.to_be_bytes());
+        chunk.extend_from_slice(&height.to_be_bytes());
+        // offset
+        chunk.extend_from_slice(&[0; 8]);
+        chunk.extend_from_slice(&millis.to_be_bytes());
+        chunk.extend_from_slice(&1000u16.to_be_bytes());
+        // dispose op none, blend op source
+        chunk.extend_from_slice(&[0, 0]);
+        writer.write_chunk(*b"fcTL", &chunk)?;
+        sequence += 1;
+
+        // the first frame is also the default image
+        if i == 0 {
+            writer.write_chunk(*b"IDAT", data)?;
+        } else {
+            chunk.clear();
+            chunk.extend_from_slice(&sequence.to_be_bytes());
+            chunk.extend_from_slice(data);
+            writer.write_chunk(*b"fdAT", &chunk)?;
+            sequence += 1;
+        }
+    }
+
+    drop(writer);
+    Ok(apng)
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    match e {
+        gif::EncodingError::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+/// Forwards frames to another target while recording them into an animation,
+/// timestamped by when they are drawn.
+pub struct Recorder<T, W: Write> {
+    inner: T,
+    writer: AnimationWriter<W>,
+    start: Option<Instant>,
+}
+
+impl<T, W: Write> Recorder<T, W> {
+    pub fn new(inner: T, writer: AnimationWriter<W>) -> Self {
+        Self {
+            inner,
+            writer,
+            start: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Completes the animation and returns the inner target.
+    pub fn finish(self) -> io::Result<T> {
+        self.writer.finish()?;
+        Ok(self.inner)
+    }
+}
+
+impl<T, W> RenderTarget<nalgebra::Vector4<f32>> for Recorder<T, W>
+where
+    T: RenderTarget<nalgebra::Vector4<f32>>,
+    W: Write,
+{
+    fn size(&self) -> [usize; 2] {
+        self.inner.size()
+    }
+
+    fn pixel_aspect_ratio(&self) -> f32 {
+        self.inner.pixel_aspect_ratio()
+    }
+
+    fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        self.inner.draw(buffer);
+
+        let timestamp = self.start.get_or_insert_with(Instant::now).elapsed();
+        self.writer
+            .write_frame(buffer, self.size()[0], timestamp)
+            .expect("failed to record frame");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+    use termishade::MemoryTarget;
+
+    fn gray(v: f32) -> Vector4<f32> {
+        Vector4::new(v, v, v, 1.0)
+    }
+
+    // 2x1 frames of different colors at 0, 50 and 150 ms
+    fn record(format: AnimationFormat) -> Vec<u8> {
+        let mut writer = AnimationWriter::new(Vec::new(), format);
+        for (i, &millis) in [0, 50, 150].iter().enumerate() {
+            let v = i as f32 / 2.0;
+            writer
+                .write_frame(&[gray(v), gray(1.0 - v)], 2, Duration::from_millis(millis))
+                .unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    // (type, data) of every chunk, checking their CRCs
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut rest = png
+            .strip_prefix(b"\x89PNG\r\n\x1A\n")
+            .expect("no signature");
+        let mut chunks = Vec::new();
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let (body, crc) = rest[4..].split_at(4 + len);
+            let mut expected = flate2::Crc::new();
+            expected.update(body);
+            assert_eq!(crc[..4], expected.sum().to_be_bytes());
+
+            chunks.push(([body[0], body[1], body[2], body[3]], body[4..].to_vec()));
+            rest = &crc[4..];
+        }
+        chunks
+    }
+
+    fn be32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn rows(data: &[u8]) -> Vec<u8> {
+        let mut rgba = Vec::new();
+        io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(data), &mut rgba).unwrap();
+        // filter type of the only row
+        assert_eq!(rgba.remove(0), 0);
+        rgba
+    }
+
+    #[test]
+    fn apng_chunks() {
+        let apng = record(AnimationFormat::Apng);
+        let chunks = chunks(&apng);
+        let types = chunks
+            .iter()
+            .map(|(t, _)| std::str::from_utf8(t).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]
+        );
+
+        // 3 frames, looping forever
+        assert_eq!(chunks[1].1, [0, 0, 0, 3, 0, 0, 0, 0]);
+
+        // frame controls and frame data share the sequence numbers
+        let sequence = chunks
+            .iter()
+            .filter(|(t, _)| t == b"fcTL" || t == b"fdAT")
+            .map(|(_, data)| be32(data))
+            .collect::<Vec<_>>();
+        assert_eq!(sequence, [0, 1, 2, 3, 4]);
+
+        // delays as numerator and denominator, the last one repeating the
+        // previous duration
+        let delays = chunks
+            .iter()
+            .filter(|(t, _)| t == b"fcTL")
+            .map(|(_, data)| {
+                assert_eq!(data[4..12], [0, 0, 0, 2, 0, 0, 0, 1]);
+                [
+                    u16::from_be_bytes([data[20], data[21]]),
+                    u16::from_be_bytes([data[22], data[23]]),
+                ]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [[50, 1000], [100, 1000], [100, 1000]]);
+
+        assert_eq!(rows(&chunks[3].1), [0, 0, 0, 255, 255, 255, 255, 255]);
+        assert_eq!(rows(&chunks[7].1[4..]), [255, 255, 255, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn apng_default_image_decodes() {
+        let apng = record(AnimationFormat::Apng);
+        let (info, mut reader) = png::Decoder::new(&apng[..]).read_info().unwrap();
+        assert_eq!([info.width, info.height], [2, 1]);
+        let mut rgba = vec![0; info.buffer_size()];
+        reader.next_frame(&mut rgba).unwrap();
+        assert_eq!(rgba, [0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    fn decode_gif(bytes: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(bytes).unwrap();
+        assert_eq!([decoder.width(), decoder.height()], [2, 1]);
+
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer.to_vec()));
+        }
+        frames
+    }
+
+    #[test]
+    fn gif_frames() {
+        let frames = decode_gif(&record(AnimationFormat::Gif));
+        assert_eq!(
+            frames,
+            [
+                (5, vec![0, 0, 0, 255, 255, 255, 255, 255]),
+                (10, vec![127, 127, 127, 255, 127, 127, 127, 255]),
+                (10, vec![255, 255, 255, 255, 0, 0, 0, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn gif_delays_are_rounded_from_timestamps() {
+        let mut writer = AnimationWriter::new(Vec::new(), AnimationFormat::Gif);
+        for &millis in &[0, 14, 28, 42] {
+            writer
+                .write_frame(&[gray(0.0); 2], 2, Duration::from_millis(millis))
+                .unwrap();
+        }
+        let delays = decode_gif(&writer.finish().unwrap())
+            .into_iter()
+            .map(|(delay, _)| delay)
+            .collect::<Vec<_>>();
+        // 1.4 centiseconds each, adding up to the rounded timestamps
+        assert_eq!(delays, [1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn frames_are_cropped_and_padded() {
+        let mut writer = AnimationWriter::new(Vec::new(), AnimationFormat::Gif);
+        writer
+            .write_frame(&[gray(1.0); 2], 2, Duration::from_millis(0))
+            .unwrap();
+        // wider and taller than the first frame
+        writer
+            .write_frame(&[gray(1.0); 6], 3, Duration::from_millis(10))
+            .unwrap();
+        // narrower
+        writer
+            .write_frame(&[gray(1.0)], 1, Duration::from_millis(20))
+            .unwrap();
+
+        let frames = decode_gif(&writer.finish().unwrap());
+        assert_eq!(frames[1].1, [255; 8]);
+        assert_eq!(frames[2].1, [255, 255, 255, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn recorder_forwards_and_records_frames() {
+        let mut out = Vec::new();
+        let writer = AnimationWriter::new(&mut out, AnimationFormat::Gif);
+        let mut recorder = Recorder::new(MemoryTarget::new(2, 1), writer);
+        assert_eq!(recorder.size(), [2, 1]);
+
+        recorder.draw(&[gray(0.0), gray(1.0)]);
+        recorder.draw(&[gray(1.0), gray(0.0)]);
+        let target = recorder.finish().unwrap();
+        assert_eq!(target.buffer(), [gray(1.0), gray(0.0)]);
+
+        let frames = decode_gif(&out);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].1, [0, 0, 0, 255, 255, 255, 255, 255]);
+        assert_eq!(frames[1].1, [255, 255, 255, 255, 0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::{self, Write};
+
+use crate::markup::{write_html, write_svg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Binary PPM (P6). Alpha is dropped.
+    Ppm,
+    Html,
+    Svg,
+}
+
+impl ImageFormat {
+    /// Guesses the format from a file extension.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "html" | "htm" => Some(ImageFormat::Html),
+            "svg" => Some(ImageFormat::Svg),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Html => "html",
+            ImageFormat::Svg => "svg",
+        }
+    }
+
+    /// Writes an image from 8-bit RGBA data, starting at the top left.
+    pub fn write<W: Write>(
+        self,
+        mut out: W,
+        [width, height]: [usize; 2],
+        rgba: &[u8],
+    ) -> io::Result<()> {
+        match self {
+            ImageFormat::Png => write_png(out, [width, height], rgba),
+            ImageFormat::Ppm => write_ppm(out, [width, height], rgba),
+            ImageFormat::Html | ImageFormat::Svg => {
+                let mut document = String::new();
+                if self == ImageFormat::Html {
+                    write_html(&mut document, [width, height], rgba, true);
+                } else {
+                    write_svg(&mut document, [width, height], rgba, true);
+                }
+                out.write_all(document.as_bytes())?;
+                out.flush()
+            }
+        }
+    }
+}
+
+pub fn write_png<W: Write>(mut out: W, [width, height]: [usize; 2], rgba: &[u8]) -> io::Result<()> {
+    // png only writes the end of the file when its writer is dropped,
+    // ignoring errors, so the file is encoded in memory first
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(rgba).map_err(png_error)?;
+    }
+
+    out.write_all(&png)?;
+    out.flush()
+}
+
+pub fn write_ppm<W: Write>(mut out: W, [width, height]: [usize; 2], rgba: &[u8]) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    let rgb = rgba
+        .chunks(4)
+        .flat_map(|p| p[..3].iter().copied())
+        .collect::<Vec<_>>();
+    out.write_all(&rgb)?;
+    out.flush()
+}
+
+pub(crate) fn png_error(e: png::EncodingError) -> io::Error {
+    match e {
+        png::EncodingError::IoError(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // accepts `limit` bytes and then fails
+    struct Full {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.limit - self.written.len());
+            if len == 0 && !buf.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+            }
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const RGBA: [u8; 8] = [255, 127, 0, 255, 0, 0, 255, 128];
+
+    #[test]
+    fn png_round_trip() {
+        let mut out = Vec::new();
+        write_png(&mut out, [2, 1], &RGBA).unwrap();
+        assert!(out.ends_with(b"IEND\xAE\x42\x60\x82"));
+
+        let decoder = png::Decoder::new(&out[..]);
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!([info.width, info.height], [2, 1]);
+        let mut rgba = vec![0; info.buffer_size()];
+        reader.next_frame(&mut rgba).unwrap();
+        assert_eq!(rgba, RGBA);
+    }
+
+    #[test]
+    fn truncated_png_is_an_error() {
+        let mut full = Vec::new();
+        write_png(&mut full, [2, 1], &RGBA).unwrap();
+
+        // only the end of the file doesn't fit
+        let mut out = Full {
+            written: Vec::new(),
+            limit: full.len() - 1,
+        };
+        assert!(write_png(&mut out, [2, 1], &RGBA).is_err());
+    }
+
+    #[test]
+    fn ppm() {
+        let mut out = Vec::new();
+        write_ppm(&mut out, [2, 1], &RGBA).unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\xFF\x7F\x00\x00\x00\xFF");
+    }
+}
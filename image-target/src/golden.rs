@@ -0,0 +1,194 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use termishade::util::to_rgba8;
+
+use crate::format::write_png;
+
+/// If this environment variable is set, reference images are overwritten
+/// with the compared frames instead of failing.
+pub const UPDATE_ENV_VAR: &str = "TERMISHADE_UPDATE_GOLDEN";
+
+const MISMATCH_COLOR: [u8; 4] = [255, 0, 0, 255];
+
+/// Compares frames with a reference PNG image, for tests.
+///
+/// When they don't match, the frame is saved next to the reference as
+/// `<name>.actual.png`, along with `<name>.diff.png`, where mismatched pixels
+/// are red and the rest is a dimmed grayscale version of the reference.
+pub struct Golden {
+    path: PathBuf,
+    tolerance: f32,
+}
+
+#[derive(Debug)]
+pub enum Mismatch {
+    /// The reference image doesn't exist. It is created from the frame.
+    Missing,
+    Size {
+        expected: [usize; 2],
+        actual: [usize; 2],
+    },
+    Pixels {
+        count: usize,
+        max_difference: u8,
+    },
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Mismatch::Missing => write!(f, "reference image is missing and has been created"),
+            Mismatch::Size { expected, actual } => write!(
+                f,
+                "size is {}x{}, expected {}x{}",
+                actual[0], actual[1], expected[0], expected[1]
+            ),
+            Mismatch::Pixels {
+                count,
+                max_difference,
+            } => write!(
+                f,
+                "{} pixels differ, by up to {}/255",
+                count, max_difference
+            ),
+        }
+    }
+}
+
+impl Golden {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            tolerance: 0.0,
+        }
+    }
+
+    /// Maximum difference in any channel for pixels to still match, from 0 to 1.
+    pub fn tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn compare(
+        &self,
+        buffer: &[nalgebra::Vector4<f32>],
+        width: usize,
+    ) -> io::Result<Option<Mismatch>> {
+        let size = [width, buffer.len() / width.max(1)];
+        let mut actual = Vec::new();
+        to_rgba8(buffer, width, &mut actual);
+
+        if std::env::var_os(UPDATE_ENV_VAR).is_some() {
+            save(&self.path, size, &actual)?;
+            return Ok(None);
+        }
+
+        let (expected_size, expected) = match read_png(&self.path) {
+            Ok(image) => image,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                save(&self.path, size, &actual)?;
+                return Ok(Some(Mismatch::Missing));
+            }
+            Err(e) => return Err(e),
+        };
+
+        if expected_size != size {
+            save(&self.sibling("actual"), size, &actual)?;
+            return Ok(Some(Mismatch::Size {
+                expected: expected_size,
+                actual: size,
+            }));
+        }
+
+        let tolerance = (self.tolerance.clamp(0.0, 1.0) * 255.0) as u8;
+        let mut count = 0;
+        let mut max_difference = 0;
+        let mut diff = Vec::with_capacity(expected.len());
+        for (e, a) in expected.chunks(4).zip(actual.chunks(4)) {
+            let difference = e
+                .iter()
+                .zip(a)
+                .map(|(e, a)| e.max(a) - e.min(a))
+                .max()
+                .unwrap();
+            if difference > tolerance {
+                count += 1;
+                max_difference = max_difference.max(difference);
+                diff.extend_from_slice(&MISMATCH_COLOR);
+            } else {
+                let luma = (e[0] as u32 * 3 + e[1] as u32 * 6 + e[2] as u32) / 10;
+                let dimmed = (luma / 3) as u8;
+                diff.extend_from_slice(&[dimmed, dimmed, dimmed, 255]);
+            }
+        }
+
+        if count == 0 {
+            return Ok(None);
+        }
+
+        save(&self.sibling("actual"), size, &actual)?;
+        save(&self.sibling("diff"), size, &diff)?;
+        Ok(Some(Mismatch::Pixels {
+            count,
+            max_difference,
+        }))
+    }
+
+    /// Panics if the frame doesn't match the reference.
+    pub fn assert_matches(&self, buffer: &[nalgebra::Vector4<f32>], width: usize) {
+        match self.compare(buffer, width) {
+            Ok(None) => {}
+            Ok(Some(mismatch)) => panic!(
+                "frame doesn't match {}: {} (set {} to update it)",
+                self.path.display(),
+                mismatch,
+                UPDATE_ENV_VAR
+            ),
+            Err(e) => panic!("failed to compare with {}: {}", self.path.display(), e),
+        }
+    }
+
+    // e.g. `cube.png` -> `cube.diff.png`
+    fn sibling(&self, kind: &str) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{}.{}.png", stem, kind))
+    }
+}
+
+fn save(path: &Path, size: [usize; 2], rgba: &[u8]) -> io::Result<()> {
+    write_png(BufWriter::new(File::create(path)?), size, rgba)
+}
+
+/// Reads a PNG image as 8-bit RGBA data, starting at the top left.
+pub fn read_png(path: impl AsRef<Path>) -> io::Result<([usize; 2], Vec<u8>)> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(png_decoding_error)?;
+
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data).map_err(png_decoding_error)?;
+
+    let pixels = data.chunks(info.line_size).flat_map(|row| {
+        row[..info.width as usize * info.color_type.samples()].chunks(info.color_type.samples())
+    });
+    let rgba = pixels
+        .flat_map(|p| match *p {
+            [l] => [l, l, l, 255],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        })
+        .collect();
+
+    Ok(([info.width as usize, info.height as usize], rgba))
+}
+
+fn png_decoding_error(e: png::DecodingError) -> io::Error {
+    match e {
+        png::DecodingError::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use termishade::util::to_rgba8;
+use termishade::RenderTarget;
+
+mod animation;
+mod format;
+mod golden;
+mod markup;
+
+pub use animation::{AnimationFormat, AnimationWriter, Recorder};
+pub use format::{write_png, write_ppm, ImageFormat};
+pub use golden::{read_png, Golden, Mismatch, UPDATE_ENV_VAR};
+pub use markup::{write_html, write_svg, MarkupFormat, MarkupTarget};
+
+/// Saves every drawn frame to an image file.
+pub struct ImageTarget {
+    width: usize,
+    height: usize,
+    path: PathBuf,
+    format: ImageFormat,
+    // index of the next frame if frames are numbered
+    frame: Option<usize>,
+    rgba: Vec<u8>,
+}
+
+impl ImageTarget {
+    /// The format is guessed from the extension of `path`, defaulting to PNG.
+    pub fn new(path: impl Into<PathBuf>, width: usize, height: usize) -> Self {
+        let path = path.into();
+        let format = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(ImageFormat::from_extension)
+            .unwrap_or(ImageFormat::Png);
+
+        Self {
+            width,
+            height,
+            path,
+            format,
+            frame: None,
+            rgba: Vec::new(),
+        }
+    }
+
+    pub fn format(mut self, format: ImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Saves every frame to a separate file, numbered starting from 0, e.g.
+    /// `frame.png` becomes `frame-00000.png`, `frame-00001.png` and so on.
+    /// Otherwise every frame overwrites the previous one.
+    pub fn numbered(mut self, numbered: bool) -> Self {
+        self.frame = if numbered { Some(0) } else { None };
+        self
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Path the next frame will be saved to.
+    pub fn next_path(&self) -> PathBuf {
+        match self.frame {
+            None => self.path.clone(),
+            Some(frame) => numbered_path(&self.path, frame),
+        }
+    }
+
+    pub fn save(&mut self, buffer: &[nalgebra::Vector4<f32>]) -> io::Result<()> {
+        assert_eq!(buffer.len(), self.width * self.height);
+
+        let file = BufWriter::new(File::create(self.next_path())?);
+        to_rgba8(buffer, self.width, &mut self.rgba);
+        self.format
+            .write(file, [self.width, self.height], &self.rgba)?;
+
+        if let Some(frame) = &mut self.frame {
+            *frame += 1;
+        }
+        Ok(())
+    }
+}
+
+fn numbered_path(path: &Path, frame: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{:05}.{}", stem, frame, ext.to_string_lossy()),
+        None => format!("{}-{:05}", stem, frame),
+    };
+    path.with_file_name(name)
+}
+
+impl RenderTarget<nalgebra::Vector4<f32>> for ImageTarget {
+    fn size(&self) -> [usize; 2] {
+        [self.width, self.height]
+    }
+
+    fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        self.save(buffer).expect("failed to save frame");
+    }
+}
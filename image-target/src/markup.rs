@@ -0,0 +1,204 @@
+use std::fmt::Write;
+
+use termishade::util::to_rgba8;
+use termishade::RenderTarget;
+
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+
+// size of a pixel in an svg document, in px
+const SVG_PIXEL_SIZE: usize = 8;
+
+// half of a monospace character, which is usually 0.6em wide and 1em tall
+// with the line height of the html output
+const HTML_PIXEL_ASPECT_RATIO: f32 = 1.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkupFormat {
+    /// Text like in a terminal, where every character shows two pixels, one above the other.
+    Html,
+    Svg,
+}
+
+/// Renders frames as HTML or SVG documents that can be embedded in web pages.
+pub struct MarkupTarget {
+    width: usize,
+    height: usize,
+    format: MarkupFormat,
+    standalone: bool,
+    rgba: Vec<u8>,
+    document: String,
+}
+
+impl MarkupTarget {
+    pub fn new(format: MarkupFormat, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            format,
+            standalone: true,
+            rgba: Vec::new(),
+            document: String::new(),
+        }
+    }
+
+    /// Whether to produce a whole document or just the element showing the
+    /// frame, e.g. to set as `innerHTML`. Enabled by default.
+    pub fn standalone(mut self, standalone: bool) -> Self {
+        self.standalone = standalone;
+        self
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// The last drawn frame.
+    pub fn document(&self) -> &str {
+        &self.document
+    }
+
+    pub fn draw_to_string(&mut self, buffer: &[nalgebra::Vector4<f32>]) -> String {
+        self.draw(buffer);
+        self.document.clone()
+    }
+}
+
+impl RenderTarget<nalgebra::Vector4<f32>> for MarkupTarget {
+    fn size(&self) -> [usize; 2] {
+        [self.width, self.height]
+    }
+
+    fn pixel_aspect_ratio(&self) -> f32 {
+        match self.format {
+            MarkupFormat::Html => HTML_PIXEL_ASPECT_RATIO,
+            MarkupFormat::Svg => 1.0,
+        }
+    }
+
+    fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        assert_eq!(buffer.len(), self.width * self.height);
+
+        to_rgba8(buffer, self.width, &mut self.rgba);
+        self.document.clear();
+        let size = [self.width, self.height];
+        match self.format {
+            MarkupFormat::Html => write_html(&mut self.document, size, &self.rgba, self.standalone),
+            MarkupFormat::Svg => write_svg(&mut self.document, size, &self.rgba, self.standalone),
+        }
+    }
+}
+
+/// Writes 8-bit RGBA data, starting at the top left, as rows of colored
+/// half blocks in a `<pre>` element. Alpha is ignored.
+pub fn write_html(out: &mut String, [width, height]: [usize; 2], rgba: &[u8], standalone: bool) {
+    if standalone {
+        out.push_str(concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+            "<style>body { margin: 0; background: #000; }</style>\n",
+            "</head>\n<body>\n",
+        ));
+    }
+    out.push_str("<pre style=\"margin: 0; font: 16px/1 monospace; background: #000;\">");
+
+    let pixel = |x: usize, y: usize| -> [u8; 3] {
+        if y < height {
+            let i = (y * width + x) * 4;
+            [rgba[i], rgba[i + 1], rgba[i + 2]]
+        } else {
+            [0; 3]
+        }
+    };
+
+    for row in 0..height.div_ceil(2) {
+        if row > 0 {
+            out.push('\n');
+        }
+
+        // cells of the same colors share a span
+        let mut x = 0;
+        while x < width {
+            let colors = [pixel(x, row * 2), pixel(x, row * 2 + 1)];
+            let len = (x..width)
+                .take_while(|&x| [pixel(x, row * 2), pixel(x, row * 2 + 1)] == colors)
+                .count();
+
+            out.push_str("<span style=\"color: ");
+            push_hex(out, colors[0]);
+            out.push_str("; background: ");
+            push_hex(out, colors[1]);
+            out.push_str("\">");
+            out.extend(std::iter::repeat_n(UPPER_HALF_BLOCK, len));
+            out.push_str("</span>");
+
+            x += len;
+        }
+    }
+
+    out.push_str("</pre>");
+    if standalone {
+        out.push_str("\n</body>\n</html>\n");
+    }
+}
+
+/// Writes 8-bit RGBA data, starting at the top left, as an SVG image with a
+/// rectangle for every run of pixels of the same color. Alpha is ignored.
+pub fn write_svg(out: &mut String, [width, height]: [usize; 2], rgba: &[u8], standalone: bool) {
+    if standalone {
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    }
+    write!(
+        out,
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" ",
+            "viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">\n",
+        ),
+        width * SVG_PIXEL_SIZE,
+        height * SVG_PIXEL_SIZE,
+        width,
+        height,
+    )
+    .unwrap();
+
+    for (y, row) in rgba.chunks(width * 4).enumerate() {
+        let mut rest = row;
+        let mut x = 0;
+        while !rest.is_empty() {
+            let color = &rest[..3];
+            let len = rest.chunks(4).take_while(|p| &p[..3] == color).count();
+
+            write!(
+                out,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"1\" fill=\"",
+                x, y, len
+            )
+            .unwrap();
+            push_hex(out, [color[0], color[1], color[2]]);
+            out.push_str("\"/>\n");
+
+            x += len;
+            rest = &rest[len * 4..];
+        }
+    }
+
+    out.push_str("</svg>\n");
+}
+
+fn push_hex(out: &mut String, [r, g, b]: [u8; 3]) {
+    write!(out, "#{:02x}{:02x}{:02x}", r, g, b).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_aspect_ratio() {
+        let html = MarkupTarget::new(MarkupFormat::Html, 4, 2);
+        assert_eq!(html.pixel_aspect_ratio(), 1.2);
+        assert_eq!(html.aspect_ratio(), 2.4);
+
+        let svg = MarkupTarget::new(MarkupFormat::Svg, 4, 2);
+        assert_eq!(svg.pixel_aspect_ratio(), 1.0);
+    }
+}
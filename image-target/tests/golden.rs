@@ -0,0 +1,176 @@
+use std::path::PathBuf;
+
+use image_target::{Golden, Mismatch};
+use nalgebra::{Vector2, Vector3, Vector4};
+use termishade::rasterizer::TriangleRasterizer;
+use termishade::{
+    blend, BaseRenderer, ColorDepthRenderer, DrawParams, MemoryTarget, NalgebraRenderer, Program,
+    RenderTarget,
+};
+
+const SIZE: [usize; 2] = [16, 12];
+
+struct ColorProgram;
+
+impl Program for ColorProgram {
+    type VertexIn = (Vector2<f32>, Vector3<f32>);
+    type VertexOut = Vector4<f32>;
+    type ColorOut = Vector4<f32>;
+    type Uniform = ();
+    type Intermediate = Vector3<f32>;
+
+    fn vertex(&self, (pos, color): &Self::VertexIn, _: &()) -> (Vector4<f32>, Vector3<f32>) {
+        (Vector4::new(pos.x, pos.y, 0.5, 1.0), *color)
+    }
+
+    fn fragment(&self, _: &Vector4<f32>, color: &Vector3<f32>, _: &()) -> Vector4<f32> {
+        color.push(1.0)
+    }
+}
+
+fn render(offset: f32) -> MemoryTarget<Vector4<f32>> {
+    let [w, h] = SIZE;
+    let mut renderer = ColorDepthRenderer::new(w, h);
+    renderer.clear_color(&Vector4::new(0.0, 0.0, 0.0, 1.0));
+    renderer.clear_depth(1.0);
+
+    let vertices = [
+        (
+            Vector2::new(-0.8 + offset, -0.8),
+            Vector3::new(1.0, 0.0, 0.0),
+        ),
+        (
+            Vector2::new(0.8 + offset, -0.6),
+            Vector3::new(0.0, 1.0, 0.0),
+        ),
+        (Vector2::new(0.0 + offset, 0.8), Vector3::new(0.0, 0.0, 1.0)),
+    ];
+    NalgebraRenderer::draw(
+        &mut renderer,
+        DrawParams {
+            program: &ColorProgram,
+            rasterizer: &TriangleRasterizer,
+            blender: &blend::Replace,
+            depth_test_enabled: true,
+        },
+        &vertices[..],
+        &(),
+    );
+
+    let mut target = MemoryTarget::new(w, h);
+    target.draw(renderer.color_buffer());
+    target
+}
+
+fn golden_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/triangle.png")
+}
+
+// a copy of the reference in a directory of its own, so that failed
+// comparisons don't leave files next to the checked in image
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("image-target-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn triangle_matches_reference() {
+    let target = render(0.0);
+    Golden::new(golden_path()).assert_matches(target.buffer(), SIZE[0]);
+}
+
+#[test]
+fn memory_target_pixels() {
+    let target = render(0.0);
+    // the center is inside, the corners are not
+    assert_ne!(
+        target.pixel([8, 5]),
+        Some(&Vector4::new(0.0, 0.0, 0.0, 1.0))
+    );
+    assert_eq!(
+        target.pixel([0, 11]),
+        Some(&Vector4::new(0.0, 0.0, 0.0, 1.0))
+    );
+    assert_eq!(target.pixel([16, 0]), None);
+    assert_eq!(target.pixel([0, 12]), None);
+
+    let empty = MemoryTarget::<Vector4<f32>>::new(4, 4);
+    assert_eq!(empty.pixel([0, 0]), None);
+}
+
+#[test]
+fn mismatch_is_reported() {
+    let dir = scratch_dir("mismatch");
+    let path = dir.join("triangle.png");
+    std::fs::copy(golden_path(), &path).unwrap();
+
+    let target = render(0.25);
+    let mismatch = Golden::new(&path)
+        .compare(target.buffer(), SIZE[0])
+        .unwrap();
+    match mismatch {
+        Some(Mismatch::Pixels {
+            count,
+            max_difference,
+        }) => {
+            assert!(count > 0);
+            assert!(max_difference > 0);
+        }
+        other => panic!("expected mismatched pixels, got {:?}", other),
+    }
+    assert!(dir.join("triangle.actual.png").exists());
+    assert!(dir.join("triangle.diff.png").exists());
+
+    // small enough differences are tolerated
+    let target = render(0.0);
+    let mut buffer = target.buffer().to_vec();
+    buffer[0].x += 0.01;
+    let golden = Golden::new(&path);
+    assert!(golden.compare(&buffer, SIZE[0]).unwrap().is_some());
+    assert!(golden
+        .tolerance(0.02)
+        .compare(&buffer, SIZE[0])
+        .unwrap()
+        .is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn size_mismatch_is_reported() {
+    let dir = scratch_dir("size");
+    let path = dir.join("triangle.png");
+    std::fs::copy(golden_path(), &path).unwrap();
+
+    let target = render(0.0);
+    let buffer = &target.buffer()[..SIZE[0] * (SIZE[1] - 1)];
+    match Golden::new(&path).compare(buffer, SIZE[0]).unwrap() {
+        Some(Mismatch::Size { expected, actual }) => {
+            assert_eq!(expected, SIZE);
+            assert_eq!(actual, [SIZE[0], SIZE[1] - 1]);
+        }
+        other => panic!("expected a size mismatch, got {:?}", other),
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn missing_reference_is_reported_and_created() {
+    let dir = scratch_dir("missing");
+    let path = dir.join("new.png");
+
+    let target = render(0.0);
+    let golden = Golden::new(&path);
+    assert!(matches!(
+        golden.compare(target.buffer(), SIZE[0]).unwrap(),
+        Some(Mismatch::Missing)
+    ));
+    assert!(path.exists());
+    // the created reference matches from then on
+    assert!(golden.compare(target.buffer(), SIZE[0]).unwrap().is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
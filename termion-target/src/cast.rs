@@ -0,0 +1,251 @@
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use termishade::RenderTarget;
+
+use crate::{ColorMode, TermionTarget};
+
+const HIDE_CURSOR: &str = "\x1B[?25l";
+
+/// Writes terminal output into an asciinema v2 `.cast` file.
+pub struct CastWriter<W: Write> {
+    out: W,
+    start: Instant,
+}
+
+impl<W: Write> CastWriter<W> {
+    /// Writes the header for a terminal of `width`x`height` cells.
+    pub fn new(mut out: W, width: usize, height: usize) -> io::Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs())
+            .unwrap_or(0);
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": timestamp,
+            "env": { "TERM": std::env::var("TERM").unwrap_or_default() },
+        });
+        writeln!(out, "{}", header)?;
+
+        Ok(Self {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    /// Records output at the time elapsed since the header was written.
+    pub fn output(&mut self, data: &str) -> io::Result<()> {
+        self.output_at(self.start.elapsed(), data)
+    }
+
+    pub fn output_at(&mut self, time: Duration, data: &str) -> io::Result<()> {
+        self.event(time, "o", data)
+    }
+
+    /// Records the terminal being resized to `width`x`height` cells.
+    pub fn resize_at(&mut self, time: Duration, width: usize, height: usize) -> io::Result<()> {
+        self.event(time, "r", &format!("{}x{}", width, height))
+    }
+
+    fn event(&mut self, time: Duration, code: &str, data: &str) -> io::Result<()> {
+        let line = serde_json::to_string(&(time.as_secs_f64(), code, data))?;
+        writeln!(self.out, "{}", line)?;
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Renders frames the same way as `TermionTarget` and records them into a
+/// `.cast` file instead of showing them, timestamped by when they are drawn.
+pub struct CastTarget<W: Write> {
+    target: TermionTarget,
+    writer: CastWriter<W>,
+    started: bool,
+}
+
+impl<W: Write> CastTarget<W> {
+    /// The size is in cells.
+    pub fn new(out: W, width: usize, height: usize) -> io::Result<Self> {
+        Ok(Self {
+            target: TermionTarget::new_without_io(width, height),
+            writer: CastWriter::new(out, width, height)?,
+            started: false,
+        })
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.target = self.target.color_mode(color_mode);
+        self
+    }
+
+    pub fn color_tolerance(mut self, tolerance: f32) -> Self {
+        self.target = self.target.color_tolerance(tolerance);
+        self
+    }
+
+    /// See `TermionTarget::with_pixel_aspect_ratio`.
+    pub fn with_pixel_aspect_ratio(mut self, ratio: f32) -> Self {
+        self.target = self.target.with_pixel_aspect_ratio(ratio);
+        self
+    }
+
+    /// Sets the size in cells and records the resize.
+    pub fn resize(&mut self, width: usize, height: usize) -> io::Result<()> {
+        self.target.resize(width, height);
+        self.writer
+            .resize_at(self.writer.start.elapsed(), width, height)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+}
+
+impl<W: Write> RenderTarget<nalgebra::Vector4<f32>> for CastTarget<W> {
+    fn size(&self) -> [usize; 2] {
+        self.target.size()
+    }
+
+    fn pixel_aspect_ratio(&self) -> f32 {
+        self.target.pixel_aspect_ratio()
+    }
+
+    fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        let mut frame = self.target.draw_changes_to_string(buffer);
+        if !self.started {
+            frame.insert_str(0, HIDE_CURSOR);
+            self.started = true;
+        }
+        if !frame.is_empty() {
+            self.writer.output(&frame).expect("failed to record frame");
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    /// Seconds since the start of the recording.
+    pub time: f64,
+    /// `"o"` for output, `"i"` for input, `"r"` for resizes and so on.
+    pub code: String,
+    pub data: String,
+}
+
+/// A recording read from a `.cast` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast {
+    pub width: usize,
+    pub height: usize,
+    pub events: Vec<CastEvent>,
+}
+
+impl Cast {
+    pub fn read<R: BufRead>(input: R) -> io::Result<Self> {
+        let mut lines = input.lines();
+
+        let header: serde_json::Value = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(invalid_data("empty cast file")),
+        };
+        if header["version"] != 2 {
+            return Err(invalid_data("unsupported cast file version"));
+        }
+        let dimension = |name: &str| {
+            header[name]
+                .as_u64()
+                .map(|n| n as usize)
+                .ok_or_else(|| invalid_data("missing terminal size"))
+        };
+        let (width, height) = (dimension("width")?, dimension("height")?);
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, code, data) = serde_json::from_str(&line)?;
+            events.push(CastEvent { time, code, data });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            events,
+        })
+    }
+
+    /// Concatenated output of all events, e.g. to compare with a known good recording.
+    pub fn output(&self) -> String {
+        self.events
+            .iter()
+            .filter(|e| e.code == "o")
+            .map(|e| e.data.as_str())
+            .collect()
+    }
+
+    /// Writes the output to `out` with the recorded timing, sped up by `speed`,
+    /// which has to be positive and finite.
+    ///
+    /// `out` is usually stdout in raw mode, so that the output is shown as is.
+    pub fn play<W: Write>(&self, out: &mut W, speed: f64) -> io::Result<()> {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "playback speed must be positive and finite",
+            ));
+        }
+
+        let start = Instant::now();
+        for event in self.events.iter().filter(|e| e.code == "o") {
+            let time = Duration::try_from_secs_f64((event.time / speed).max(0.0))
+                .map_err(|_| invalid_data("event time is out of range"))?;
+            if let Some(delay) = time.checked_sub(start.elapsed()) {
+                std::thread::sleep(delay);
+            }
+            out.write_all(event.data.as_bytes())?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cast() -> Cast {
+        let file = "{\"version\": 2, \"width\": 4, \"height\": 2}\n\
+                    [0.0, \"o\", \"a\"]\n\
+                    [0.001, \"r\", \"5x2\"]\n\
+                    [0.002, \"o\", \"b\"]\n";
+        Cast::read(file.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn plays_output_events() {
+        let mut out = Vec::new();
+        cast().play(&mut out, 1000.0).unwrap();
+        assert_eq!(out, b"ab");
+    }
+
+    #[test]
+    fn rejects_invalid_speeds() {
+        for &speed in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let mut out = Vec::new();
+            let error = cast().play(&mut out, speed).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(out.is_empty());
+        }
+    }
+}
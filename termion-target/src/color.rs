@@ -0,0 +1,177 @@
+use std::io::Write;
+
+use termion::color::{AnsiValue, DetectColors};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+// xterm defaults for the 16 basic colors
+const ANSI16_PALETTE: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+impl ColorMode {
+    /// Guesses the color mode from `COLORTERM` and `TERM`.
+    ///
+    /// Returns `None` if the environment doesn't say anything conclusive.
+    pub fn from_env() -> Option<ColorMode> {
+        ColorMode::from_vars(
+            &std::env::var("COLORTERM").unwrap_or_default(),
+            &std::env::var("TERM").unwrap_or_default(),
+        )
+    }
+
+    fn from_vars(colorterm: &str, term: &str) -> Option<ColorMode> {
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return Some(ColorMode::TrueColor);
+        }
+
+        if term.ends_with("-direct") || term.contains("truecolor") || term.contains("24bit") {
+            Some(ColorMode::TrueColor)
+        } else if term.contains("256color") {
+            Some(ColorMode::Ansi256)
+        } else {
+            None
+        }
+    }
+
+    /// Picks the best color mode, falling back to querying the terminal
+    /// through `out` if the environment is inconclusive.
+    ///
+    /// `out` should be in raw mode, otherwise the terminal's answer is echoed.
+    pub fn detect<W: Write>(out: &mut W) -> ColorMode {
+        ColorMode::from_env().unwrap_or_else(|| match out.available_colors() {
+            Ok(n) if n >= 256 => ColorMode::Ansi256,
+            _ => ColorMode::Ansi16,
+        })
+    }
+
+    pub fn quantize(self, pixel: nalgebra::Vector4<f32>) -> CellColor {
+        let pixel = pixel.map(|a| a.clamp(0.0, 1.0));
+
+        match self {
+            ColorMode::TrueColor => {
+                let p = pixel.map(|a| (a * 255.0) as u8);
+                CellColor::Rgb(p.x, p.y, p.z)
+            }
+            ColorMode::Ansi256 => {
+                let p = pixel.map(|a| (a * 5.0) as u8);
+                CellColor::Ansi(AnsiValue::rgb(p.x, p.y, p.z).0)
+            }
+            ColorMode::Ansi16 => {
+                let p = pixel.xyz() * 255.0;
+                let idx = ANSI16_PALETTE
+                    .iter()
+                    .map(|&[r, g, b]| {
+                        (nalgebra::Vector3::new(r as f32, g as f32, b as f32) - p).norm_squared()
+                    })
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+                    .map(|(i, _)| i)
+                    .unwrap_or(0);
+                CellColor::Basic(idx as u8)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellColor {
+    Rgb(u8, u8, u8),
+    Ansi(u8),
+    Basic(u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    #[test]
+    fn colorterm_wins() {
+        for colorterm in &["truecolor", "24bit"] {
+            assert_eq!(
+                ColorMode::from_vars(colorterm, "xterm"),
+                Some(ColorMode::TrueColor)
+            );
+            assert_eq!(
+                ColorMode::from_vars(colorterm, "xterm-256color"),
+                Some(ColorMode::TrueColor)
+            );
+        }
+    }
+
+    #[test]
+    fn term() {
+        let mode = |term| ColorMode::from_vars("", term);
+        assert_eq!(mode("xterm-direct"), Some(ColorMode::TrueColor));
+        assert_eq!(mode("xterm-truecolor"), Some(ColorMode::TrueColor));
+        assert_eq!(mode("konsole-24bit"), Some(ColorMode::TrueColor));
+        assert_eq!(mode("xterm-256color"), Some(ColorMode::Ansi256));
+        assert_eq!(mode("screen-256color"), Some(ColorMode::Ansi256));
+        assert_eq!(mode("xterm"), None);
+        assert_eq!(mode(""), None);
+        // other values of COLORTERM don't tell anything
+        assert_eq!(ColorMode::from_vars("yes", "xterm"), None);
+    }
+
+    #[test]
+    fn quantize_true_color() {
+        let mode = ColorMode::TrueColor;
+        assert_eq!(
+            mode.quantize(Vector4::new(1.0, 0.5, 0.0, 1.0)),
+            CellColor::Rgb(255, 127, 0)
+        );
+        // out of range channels are clamped
+        assert_eq!(
+            mode.quantize(Vector4::new(2.0, -1.0, 0.0, 1.0)),
+            CellColor::Rgb(255, 0, 0)
+        );
+    }
+
+    #[test]
+    fn quantize_ansi256() {
+        let mode = ColorMode::Ansi256;
+        // the 6x6x6 cube starts at 16
+        assert_eq!(mode.quantize(Vector4::zeros()), CellColor::Ansi(16));
+        assert_eq!(mode.quantize(Vector4::repeat(1.0)), CellColor::Ansi(231));
+        assert_eq!(
+            mode.quantize(Vector4::new(1.0, 0.0, 0.0, 1.0)),
+            CellColor::Ansi(16 + 36 * 5)
+        );
+    }
+
+    #[test]
+    fn quantize_ansi16() {
+        let mode = ColorMode::Ansi16;
+        assert_eq!(mode.quantize(Vector4::zeros()), CellColor::Basic(0));
+        assert_eq!(mode.quantize(Vector4::repeat(1.0)), CellColor::Basic(15));
+        assert_eq!(
+            mode.quantize(Vector4::new(0.8, 0.0, 0.0, 1.0)),
+            CellColor::Basic(1)
+        );
+        assert_eq!(
+            mode.quantize(Vector4::new(1.0, 0.0, 0.0, 1.0)),
+            CellColor::Basic(9)
+        );
+    }
+}
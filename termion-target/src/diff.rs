@@ -0,0 +1,41 @@
+use std::ops::Range;
+
+use crate::color::CellColor;
+
+/// Unchanged cells between two changed ones are re-emitted instead of
+/// jumping over them if there are at most this many.
+const MAX_MERGED_GAP: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cell {
+    pub color: CellColor,
+    pub source: nalgebra::Vector4<f32>,
+}
+
+impl Cell {
+    /// Whether replacing `self` with `new` would make a visible difference.
+    ///
+    /// Changes in the source color of at most `tolerance` in every channel are ignored.
+    pub fn differs(&self, new: &Cell, tolerance: f32) -> bool {
+        self.color != new.color && (self.source - new.source).xyz().amax() > tolerance
+    }
+}
+
+/// Ranges of cells in `new` that have to be redrawn, with short gaps merged.
+pub fn changed_runs(front: &[Cell], new: &[Cell], tolerance: f32) -> Vec<Range<usize>> {
+    let changed = front
+        .iter()
+        .zip(new)
+        .map(|(old, new)| old.differs(new, tolerance))
+        .collect::<Vec<_>>();
+
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (x, _) in changed.iter().enumerate().filter(|(_, c)| **c) {
+        match runs.last_mut() {
+            Some(run) if x - run.end <= MAX_MERGED_GAP => run.end = x + 1,
+            _ => runs.push(x..x + 1),
+        }
+    }
+
+    runs
+}
@@ -0,0 +1,462 @@
+use crate::color::CellColor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Attrs(u8);
+
+impl Attrs {
+    pub const BOLD: Attrs = Attrs(1 << 0);
+    pub const DIM: Attrs = Attrs(1 << 1);
+    pub const ITALIC: Attrs = Attrs(1 << 2);
+    pub const UNDERLINE: Attrs = Attrs(1 << 3);
+    pub const BLINK: Attrs = Attrs(1 << 4);
+    pub const REVERSE: Attrs = Attrs(1 << 5);
+
+    // (attribute, SGR code to set it, SGR code to unset it)
+    const CODES: [(Attrs, u8, u8); 6] = [
+        (Attrs::BOLD, 1, 22),
+        (Attrs::DIM, 2, 22),
+        (Attrs::ITALIC, 3, 23),
+        (Attrs::UNDERLINE, 4, 24),
+        (Attrs::BLINK, 5, 25),
+        (Attrs::REVERSE, 7, 27),
+    ];
+
+    pub fn empty() -> Self {
+        Attrs(0)
+    }
+
+    pub fn contains(self, other: Attrs) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Attrs) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl std::ops::BitOr for Attrs {
+    type Output = Attrs;
+
+    fn bitor(self, other: Attrs) -> Attrs {
+        Attrs(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitAnd for Attrs {
+    type Output = Attrs;
+
+    fn bitand(self, other: Attrs) -> Attrs {
+        Attrs(self.0 & other.0)
+    }
+}
+
+impl std::ops::Not for Attrs {
+    type Output = Attrs;
+
+    fn not(self) -> Attrs {
+        Attrs(!self.0)
+    }
+}
+
+/// SGR state of the terminal. `None` colors are the terminal's defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Option<CellColor>,
+    pub bg: Option<CellColor>,
+    pub attrs: Attrs,
+}
+
+/// Writes escape sequences into a reusable buffer, keeping track of the
+/// cursor position and SGR state so that only the necessary changes are sent.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+    scratch: Vec<u8>,
+    // `None` if unknown
+    style: Option<Style>,
+    cursor: Option<[usize; 2]>,
+    // where the next cell will be written
+    pos: [usize; 2],
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Clears the buffer, keeping the tracked terminal state.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Forgets the cursor position and SGR state, e.g. after something else
+    /// has written to the terminal.
+    pub fn reset_state(&mut self) {
+        self.style = None;
+        self.cursor = None;
+    }
+
+    /// Appends a sequence that doesn't move the cursor or change SGR state.
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn move_to(&mut self, x: usize, y: usize) {
+        self.pos = [x, y];
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        if self.style == Some(style) {
+            return;
+        }
+
+        let start = self.buf.len();
+        self.buf.extend_from_slice(b"\x1B[");
+
+        self.scratch.clear();
+        self.scratch.push(b'0');
+        push_style_params(&mut self.scratch, &Style::default(), &style);
+
+        let incremental = self.style.map(|current| {
+            push_style_params(&mut self.buf, &current, &style);
+            // the first parameter doesn't need a separator
+            self.buf.remove(start + 2);
+            self.buf.len() - start - 2
+        });
+
+        match incremental {
+            Some(len) if len <= self.scratch.len() => {}
+            _ => {
+                self.buf.truncate(start + 2);
+                self.buf.extend_from_slice(&self.scratch);
+            }
+        }
+        self.buf.push(b'm');
+
+        self.style = Some(style);
+    }
+
+    /// Writes `n` blank cells in the current background color.
+    pub fn blank(&mut self, n: usize) {
+        self.sync_cursor();
+
+        // erasing doesn't move the cursor, so it'll have to be moved afterwards
+        let erase_cost = 2 * (3 + num_len(n));
+        if n > erase_cost {
+            self.csi(n, b'X');
+        } else {
+            self.spaces(n);
+        }
+        self.pos[0] += n;
+    }
+
+    /// Like `blank`, but the cells are known to extend to the end of the line.
+    pub fn blank_to_end(&mut self, n: usize) {
+        self.sync_cursor();
+
+        if n > 3 {
+            self.buf.extend_from_slice(b"\x1B[K");
+        } else {
+            self.spaces(n);
+        }
+        self.pos[0] += n;
+    }
+
+    fn spaces(&mut self, n: usize) {
+        self.buf.resize(self.buf.len() + n, b' ');
+        if let Some(cursor) = &mut self.cursor {
+            cursor[0] += n;
+        }
+    }
+
+    fn sync_cursor(&mut self) {
+        let [x, y] = self.pos;
+
+        match self.cursor {
+            Some(cursor) if cursor == self.pos => {}
+            Some([_, cy]) if cy == y && x == 0 => self.buf.push(b'\r'),
+            Some([_, cy]) if cy + 1 == y && x == 0 => self.buf.extend_from_slice(b"\r\n"),
+            Some([cx, cy]) if cy == y && cx < x && num_len(x - cx) <= num_len(x + 1) => {
+                self.csi(x - cx, b'C')
+            }
+            Some([_, cy]) if cy == y => self.csi(x + 1, b'G'),
+            _ => {
+                self.buf.extend_from_slice(b"\x1B[");
+                if [x, y] != [0, 0] {
+                    push_num(&mut self.buf, y + 1);
+                }
+                if x != 0 {
+                    self.buf.push(b';');
+                    push_num(&mut self.buf, x + 1);
+                }
+                self.buf.push(b'H');
+            }
+        }
+
+        self.cursor = Some(self.pos);
+    }
+
+    // CSI with a single parameter, which is omitted if it's the default 1
+    fn csi(&mut self, n: usize, cmd: u8) {
+        self.buf.extend_from_slice(b"\x1B[");
+        if n != 1 {
+            push_num(&mut self.buf, n);
+        }
+        self.buf.push(cmd);
+    }
+}
+
+fn push_style_params(out: &mut Vec<u8>, from: &Style, to: &Style) {
+    let mut to_add = to.attrs & !from.attrs;
+    let to_remove = from.attrs & !to.attrs;
+
+    // bold and dim are unset by the same code
+    if to_remove.intersects(Attrs::BOLD | Attrs::DIM) {
+        to_add = to_add | (to.attrs & (Attrs::BOLD | Attrs::DIM));
+    }
+
+    let mut last_unset = None;
+    for &(attr, _, unset) in &Attrs::CODES {
+        if to_remove.contains(attr) && last_unset != Some(unset) {
+            push_param(out, unset as usize);
+            last_unset = Some(unset);
+        }
+    }
+    for &(attr, set, _) in &Attrs::CODES {
+        if to_add.contains(attr) {
+            push_param(out, set as usize);
+        }
+    }
+
+    if from.fg != to.fg {
+        push_color(out, to.fg, false);
+    }
+    if from.bg != to.bg {
+        push_color(out, to.bg, true);
+    }
+}
+
+fn push_color(out: &mut Vec<u8>, color: Option<CellColor>, bg: bool) {
+    let base = if bg { 40 } else { 30 };
+
+    match color {
+        None => push_param(out, base + 9),
+        Some(CellColor::Rgb(r, g, b)) => {
+            push_param(out, base + 8);
+            push_param(out, 2);
+            push_param(out, r as usize);
+            push_param(out, g as usize);
+            push_param(out, b as usize);
+        }
+        Some(CellColor::Ansi(n)) => {
+            push_param(out, base + 8);
+            push_param(out, 5);
+            push_param(out, n as usize);
+        }
+        // 256-color sequences don't work on 16-color terminals
+        Some(CellColor::Basic(n)) if n < 8 => push_param(out, base + n as usize),
+        Some(CellColor::Basic(n)) => push_param(out, base + 60 + n as usize - 8),
+    }
+}
+
+fn push_param(out: &mut Vec<u8>, n: usize) {
+    out.push(b';');
+    push_num(out, n);
+}
+
+fn push_num(out: &mut Vec<u8>, mut n: usize) {
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&digits[i..]);
+}
+
+fn num_len(mut n: usize) -> usize {
+    let mut len = 1;
+    while n >= 10 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an encoder that knows the cursor is at `[x, y]`
+    fn at(x: usize, y: usize) -> Encoder {
+        let mut e = Encoder::new();
+        e.move_to(x, y);
+        e.blank(0);
+        e.clear();
+        e
+    }
+
+    fn style(fg: Option<CellColor>, bg: Option<CellColor>, attrs: Attrs) -> Style {
+        Style { fg, bg, attrs }
+    }
+
+    #[test]
+    fn full_style_when_state_is_unknown() {
+        let mut e = Encoder::new();
+        e.set_style(style(Some(CellColor::Rgb(1, 2, 3)), None, Attrs::BOLD));
+        assert_eq!(e.bytes(), b"\x1B[0;1;38;2;1;2;3m");
+    }
+
+    #[test]
+    fn same_style_is_not_repeated() {
+        let mut e = Encoder::new();
+        let s = style(Some(CellColor::Ansi(100)), None, Attrs::empty());
+        e.set_style(s);
+        e.clear();
+        e.set_style(s);
+        assert_eq!(e.bytes(), b"");
+    }
+
+    #[test]
+    fn incremental_style_when_shorter() {
+        let mut e = Encoder::new();
+        let s = style(Some(CellColor::Rgb(1, 2, 3)), None, Attrs::BOLD);
+        e.set_style(s);
+        e.clear();
+        e.set_style(Style {
+            bg: Some(CellColor::Basic(4)),
+            ..s
+        });
+        assert_eq!(e.bytes(), b"\x1B[44m");
+
+        e.clear();
+        e.set_style(Style {
+            bg: Some(CellColor::Basic(12)),
+            ..s
+        });
+        assert_eq!(e.bytes(), b"\x1B[104m");
+    }
+
+    #[test]
+    fn reset_when_shorter() {
+        let mut e = Encoder::new();
+        e.set_style(style(
+            Some(CellColor::Ansi(1)),
+            None,
+            Attrs::BOLD | Attrs::ITALIC | Attrs::UNDERLINE,
+        ));
+        e.clear();
+        e.set_style(Style::default());
+        assert_eq!(e.bytes(), b"\x1B[0m");
+    }
+
+    #[test]
+    fn unsetting_bold_keeps_dim() {
+        let mut e = Encoder::new();
+        e.set_style(style(None, None, Attrs::BOLD | Attrs::DIM | Attrs::ITALIC));
+        e.clear();
+        // 22 unsets both bold and dim, so dim has to be set again
+        e.set_style(style(None, None, Attrs::DIM | Attrs::ITALIC));
+        assert_eq!(e.bytes(), b"\x1B[22;2m");
+
+        e.clear();
+        e.set_style(style(None, None, Attrs::empty()));
+        assert_eq!(e.bytes(), b"\x1B[0m");
+    }
+
+    #[test]
+    fn absolute_position_when_cursor_is_unknown() {
+        let mut e = Encoder::new();
+        e.move_to(3, 1);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[2;4H ");
+
+        let mut e = Encoder::new();
+        e.move_to(0, 0);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[H ");
+
+        let mut e = Encoder::new();
+        e.move_to(0, 5);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[6H ");
+    }
+
+    #[test]
+    fn relative_moves() {
+        // already there
+        let mut e = at(4, 1);
+        e.blank(1);
+        assert_eq!(e.bytes(), b" ");
+
+        // forward on the same line
+        let mut e = at(4, 1);
+        e.move_to(10, 1);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[6C ");
+
+        let mut e = at(4, 1);
+        e.move_to(5, 1);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[C ");
+
+        // backward on the same line
+        let mut e = at(40, 1);
+        e.move_to(2, 1);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[3G ");
+
+        // start of this line and the next one
+        let mut e = at(40, 1);
+        e.move_to(0, 1);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\r ");
+
+        let mut e = at(40, 1);
+        e.move_to(0, 2);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\r\n ");
+
+        // any other line
+        let mut e = at(40, 1);
+        e.move_to(7, 2);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[3;8H ");
+    }
+
+    #[test]
+    fn short_blanks_are_spaces() {
+        let mut e = at(0, 0);
+        e.blank(8);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"         ");
+    }
+
+    #[test]
+    fn long_blanks_are_erased() {
+        // ECH doesn't move the cursor, so the next cell needs a move
+        let mut e = at(0, 0);
+        e.blank(9);
+        e.blank(1);
+        assert_eq!(e.bytes(), b"\x1B[9X\x1B[9C ");
+    }
+
+    #[test]
+    fn blanks_to_the_end_of_the_line() {
+        let mut e = at(2, 0);
+        e.blank_to_end(3);
+        assert_eq!(e.bytes(), b"   ");
+
+        let mut e = at(2, 0);
+        e.blank_to_end(4);
+        assert_eq!(e.bytes(), b"\x1B[K");
+    }
+}
@@ -0,0 +1,80 @@
+use termion::event::Key;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Key(Key),
+    Mouse(MouseEvent),
+    /// The terminal was resized. Carries the new `RenderTarget::size`.
+    Resize([usize; 2]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release,
+    /// Movement with a button held.
+    Drag(MouseButton),
+    /// Movement with no buttons held. Terminals without any-event tracking
+    /// (mode 1003) don't report it.
+    Move,
+    ScrollUp,
+    ScrollDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    /// Zero-based cell coordinates, starting from the top left.
+    pub cell: [usize; 2],
+    /// Coordinates in the color buffer passed to `RenderTarget::draw`, i.e.
+    /// starting from the bottom left and measured in its pixels.
+    ///
+    /// These are precise if the terminal reports mouse positions in pixels,
+    /// and point to the center of the cell otherwise.
+    pub pos: [f32; 2],
+}
+
+/// Parses an SGR mouse report (`ESC [ < Cb ; Cx ; Cy M` or `... m`) into the
+/// action and the one-based reported coordinates.
+pub fn parse_sgr_mouse(raw: &[u8]) -> Option<(MouseAction, [usize; 2])> {
+    let raw = std::str::from_utf8(raw.strip_prefix(b"\x1B[<")?).ok()?;
+    let release = raw.ends_with('m');
+    let mut nums = raw[..raw.len().checked_sub(1)?]
+        .split(';')
+        .map(|n| n.parse::<usize>().ok());
+
+    let cb = nums.next()??;
+    let x = nums.next()??;
+    let y = nums.next()??;
+
+    let button = match cb & 0b11 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+    // the bits for modifier keys are ignored
+    let motion = cb & 32 != 0;
+    let wheel = cb & 64 != 0;
+
+    let action = match (wheel, motion, button) {
+        (true, _, Some(MouseButton::Left)) => MouseAction::ScrollUp,
+        (true, _, Some(MouseButton::Middle)) => MouseAction::ScrollDown,
+        // horizontal scrolling
+        (true, _, _) => return None,
+        (false, _, _) if release => MouseAction::Release,
+        (false, true, Some(button)) => MouseAction::Drag(button),
+        (false, true, None) => MouseAction::Move,
+        (false, false, Some(button)) => MouseAction::Press(button),
+        (false, false, None) => MouseAction::Release,
+    };
+
+    Some((action, [x, y]))
+}
@@ -0,0 +1,191 @@
+use std::io::{self, Write};
+
+use termishade::util::to_rgba8;
+use termishade::RenderTarget;
+
+use crate::query;
+
+/// Size of the displayed image along one axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    /// The size of the image itself.
+    Auto,
+    Cells(usize),
+    Pixels(usize),
+    /// Percentage of the terminal's size.
+    Percent(usize),
+}
+
+impl std::fmt::Display for Dimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Dimension::Auto => write!(f, "auto"),
+            Dimension::Cells(n) => write!(f, "{}", n),
+            Dimension::Pixels(n) => write!(f, "{}px", n),
+            Dimension::Percent(n) => write!(f, "{}%", n),
+        }
+    }
+}
+
+/// Draws frames as PNG images at the top left of the terminal using the
+/// iTerm2 inline image protocol.
+///
+/// `out` is usually stdout in raw mode, possibly on the alternate screen.
+pub struct ITermTarget<W: Write> {
+    width: usize,
+    height: usize,
+    display_size: [Dimension; 2],
+    preserve_aspect_ratio: bool,
+    out: W,
+    buf: Vec<u8>,
+    rgba: Vec<u8>,
+    png: Vec<u8>,
+}
+
+impl<W: Write> ITermTarget<W> {
+    /// Creates a target covering the terminal, except for the last row of
+    /// cells so that the image doesn't make it scroll.
+    pub fn new(out: W) -> io::Result<Self> {
+        let [w, h] = query::terminal_size_pixels()
+            .ok_or_else(|| io::Error::other("terminal size in pixels is unknown"))?;
+        let (cols, rows) = termion::terminal_size()?;
+        let rows = rows.max(2) as usize;
+
+        Ok(Self::with_size(out, w, h - h / rows)
+            .display_size(Dimension::Cells(cols as usize), Dimension::Cells(rows - 1)))
+    }
+
+    pub fn with_size(out: W, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            display_size: [Dimension::Auto; 2],
+            preserve_aspect_ratio: false,
+            out,
+            buf: Vec::new(),
+            rgba: Vec::new(),
+            png: Vec::new(),
+        }
+    }
+
+    /// Scales the image when displaying it, e.g. to render at a low resolution
+    /// and still fill a given number of cells.
+    pub fn display_size(mut self, width: Dimension, height: Dimension) -> Self {
+        self.display_size = [width, height];
+        self
+    }
+
+    pub fn preserve_aspect_ratio(mut self, preserve: bool) -> Self {
+        self.preserve_aspect_ratio = preserve;
+        self
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Encodes a frame as an escape sequence, preceded by moving the cursor home.
+    pub fn encode(&mut self, buffer: &[nalgebra::Vector4<f32>]) -> &[u8] {
+        let (width, height) = (self.width, self.height);
+        assert_eq!(buffer.len(), width * height);
+
+        to_rgba8(buffer, width, &mut self.rgba);
+
+        self.png.clear();
+        {
+            let mut encoder = png::Encoder::new(&mut self.png, width as u32, height as u32);
+            encoder.set_color(png::ColorType::RGBA);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_compression(png::Compression::Fast);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&self.rgba).unwrap();
+        }
+
+        let [display_width, display_height] = self.display_size;
+        self.buf.clear();
+        write!(
+            self.buf,
+            "\x1B[H\x1B]1337;File=inline=1;size={};width={};height={};preserveAspectRatio={}:",
+            self.png.len(),
+            display_width,
+            display_height,
+            self.preserve_aspect_ratio as u8,
+        )
+        .unwrap();
+        let start = self.buf.len();
+        self.buf.resize(start + self.png.len().div_ceil(3) * 4, 0);
+        base64::encode_config_slice(&self.png, base64::STANDARD, &mut self.buf[start..]);
+        self.buf.push(b'\x07');
+
+        &self.buf
+    }
+}
+
+impl<W: Write> RenderTarget<nalgebra::Vector4<f32>> for ITermTarget<W> {
+    fn size(&self) -> [usize; 2] {
+        [self.width, self.height]
+    }
+
+    /// Pixels are stretched when the image is scaled to a display size that
+    /// doesn't preserve its aspect ratio.
+    fn pixel_aspect_ratio(&self) -> f32 {
+        // the size of the terminal in pixels and in cells
+        let terminal = || {
+            let pixels = query::terminal_size_pixels()?;
+            let (cols, rows) = termion::terminal_size().ok()?;
+            Some([pixels, [cols as usize, rows as usize]])
+        };
+        let displayed = |dimension, axis: usize| -> Option<f32> {
+            match dimension {
+                // iTerm2 scales the other dimension proportionally
+                Dimension::Auto => None,
+                Dimension::Pixels(n) => Some(n as f32),
+                Dimension::Cells(n) => {
+                    let [pixels, cells] = terminal()?;
+                    Some(n as f32 * pixels[axis] as f32 / cells[axis].max(1) as f32)
+                }
+                Dimension::Percent(n) => Some(terminal()?[0][axis] as f32 * n as f32 / 100.0),
+            }
+        };
+
+        if self.preserve_aspect_ratio {
+            return 1.0;
+        }
+        let [width, height] = self.display_size;
+        let ratio = || {
+            let x = displayed(width, 0)? / self.width as f32;
+            let y = displayed(height, 1)? / self.height as f32;
+            Some(x / y)
+        };
+        ratio().filter(|r| r.is_finite() && *r > 0.0).unwrap_or(1.0)
+    }
+
+    fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        self.encode(buffer);
+        self.out.write_all(&self.buf).unwrap();
+        self.out.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_aspect_ratio() {
+        let target = ITermTarget::with_size(Vec::new(), 10, 10);
+        assert_eq!(target.pixel_aspect_ratio(), 1.0);
+
+        let target = target.display_size(Dimension::Pixels(40), Dimension::Pixels(20));
+        assert_eq!(target.pixel_aspect_ratio(), 2.0);
+
+        let target = target.display_size(Dimension::Pixels(40), Dimension::Auto);
+        assert_eq!(target.pixel_aspect_ratio(), 1.0);
+
+        let target = target
+            .display_size(Dimension::Pixels(40), Dimension::Pixels(20))
+            .preserve_aspect_ratio(true);
+        assert_eq!(target.pixel_aspect_ratio(), 1.0);
+    }
+}
@@ -0,0 +1,240 @@
+use std::io::{self, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use termishade::util::to_rgba8;
+use termishade::RenderTarget;
+
+use crate::query;
+
+// maximum size of a base64 chunk allowed by the protocol
+const CHUNK_SIZE: usize = 4096;
+
+/// Draws frames at the top left of the terminal using the kitty graphics protocol.
+///
+/// Every frame replaces the same image, so that animation doesn't leave old
+/// frames behind. `out` is usually stdout in raw mode, possibly on the alternate screen.
+pub struct KittyTarget<W: Write> {
+    width: usize,
+    height: usize,
+    image_id: u32,
+    compress: bool,
+    out: W,
+    buf: Vec<u8>,
+    rgba: Vec<u8>,
+    compressed: Vec<u8>,
+    encoded: String,
+}
+
+impl<W: Write> KittyTarget<W> {
+    /// Creates a target covering the whole terminal.
+    pub fn new(out: W) -> io::Result<Self> {
+        let [w, h] = query::terminal_size_pixels()
+            .ok_or_else(|| io::Error::other("terminal size in pixels is unknown"))?;
+        Ok(Self::with_size(out, w, h))
+    }
+
+    pub fn with_size(out: W, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            image_id: 1,
+            compress: false,
+            out,
+            buf: Vec::new(),
+            rgba: Vec::new(),
+            compressed: Vec::new(),
+            encoded: String::new(),
+        }
+    }
+
+    /// Id of the image to replace with every frame. Must be nonzero.
+    pub fn image_id(mut self, id: u32) -> Self {
+        assert_ne!(id, 0);
+        self.image_id = id;
+        self
+    }
+
+    /// Compresses frames with zlib, trading CPU time for bandwidth.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Encodes a frame as escape sequences, preceded by moving the cursor home.
+    pub fn encode(&mut self, buffer: &[nalgebra::Vector4<f32>]) -> &[u8] {
+        let (width, height) = (self.width, self.height);
+        assert_eq!(buffer.len(), width * height);
+
+        to_rgba8(buffer, width, &mut self.rgba);
+
+        self.encoded.clear();
+        if self.compress {
+            self.compressed.clear();
+            let mut encoder =
+                ZlibEncoder::new(std::mem::take(&mut self.compressed), Compression::fast());
+            encoder.write_all(&self.rgba).unwrap();
+            self.compressed = encoder.finish().unwrap();
+            base64::encode_config_buf(&self.compressed, base64::STANDARD, &mut self.encoded);
+        } else {
+            base64::encode_config_buf(&self.rgba, base64::STANDARD, &mut self.encoded);
+        }
+
+        self.buf.clear();
+        self.buf.extend_from_slice(b"\x1B[H");
+
+        // base64 is ascii, so it can be split anywhere
+        let chunks = self.encoded.as_bytes().chunks(CHUNK_SIZE);
+        let num_chunks = chunks.len();
+        for (i, chunk) in chunks.enumerate() {
+            self.buf.extend_from_slice(b"\x1B_G");
+            if i == 0 {
+                // transmit and display, don't move the cursor, don't respond
+                write!(
+                    self.buf,
+                    "a=T,f=32,s={},v={},i={},p=1,C=1,q=2,",
+                    width, height, self.image_id
+                )
+                .unwrap();
+                if self.compress {
+                    self.buf.extend_from_slice(b"o=z,");
+                }
+            }
+            let more = i + 1 < num_chunks;
+            write!(self.buf, "m={};", more as u8).unwrap();
+            self.buf.extend_from_slice(chunk);
+            self.buf.extend_from_slice(b"\x1B\\");
+        }
+
+        &self.buf
+    }
+}
+
+impl<W: Write> RenderTarget<nalgebra::Vector4<f32>> for KittyTarget<W> {
+    fn size(&self) -> [usize; 2] {
+        [self.width, self.height]
+    }
+
+    fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        self.encode(buffer);
+        self.out.write_all(&self.buf).unwrap();
+        self.out.flush().unwrap();
+    }
+}
+
+impl<W: Write> Drop for KittyTarget<W> {
+    fn drop(&mut self) {
+        // delete the image along with its data
+        let _ = write!(self.out, "\x1B_Ga=d,d=I,i={},q=2\x1B\\", self.image_id);
+        let _ = self.out.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (control data, payload) of every escape sequence after moving home
+    fn parse(bytes: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let bytes = bytes.strip_prefix(b"\x1B[H").expect("no cursor home");
+        let text = std::str::from_utf8(bytes).unwrap();
+        let mut commands = Vec::new();
+        for command in text.split_terminator("\x1B\\") {
+            let command = command
+                .strip_prefix("\x1B_G")
+                .expect("not a graphics command");
+            let (control, payload) = command.split_at(command.find(';').unwrap());
+            commands.push((control.to_string(), payload.as_bytes()[1..].to_vec()));
+        }
+        commands
+    }
+
+    fn frame(width: usize, height: usize) -> Vec<nalgebra::Vector4<f32>> {
+        vec![nalgebra::Vector4::new(1.0, 0.5, 0.0, 1.0); width * height]
+    }
+
+    #[test]
+    fn small_frame_is_one_chunk() {
+        let mut target = KittyTarget::with_size(Vec::new(), 2, 1);
+        let commands = parse(target.encode(&frame(2, 1)));
+
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].0, "a=T,f=32,s=2,v=1,i=1,p=1,C=1,q=2,m=0");
+        let rgba = base64::decode(&commands[0].1).unwrap();
+        assert_eq!(rgba, [255, 127, 0, 255, 255, 127, 0, 255]);
+    }
+
+    #[test]
+    fn chunks_are_at_most_4096_bytes() {
+        // 768 pixels are exactly 4096 bytes of base64
+        let mut target = KittyTarget::with_size(Vec::new(), 32, 24);
+        let commands = parse(target.encode(&frame(32, 24)));
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].1.len(), 4096);
+        assert!(commands[0].0.ends_with("m=0"));
+
+        // one more pixel spills into a second chunk
+        let mut target = KittyTarget::with_size(Vec::new(), 769, 1);
+        let commands = parse(target.encode(&frame(769, 1)));
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].1.len(), 4096);
+        assert_eq!(commands[1].1.len(), 8);
+    }
+
+    #[test]
+    fn continuation_flags() {
+        let mut target = KittyTarget::with_size(Vec::new(), 40, 40);
+        let encoded = target.encode(&frame(40, 40)).to_vec();
+        let commands = parse(&encoded);
+        assert_eq!(commands.len(), 3);
+
+        // only the first chunk has the image parameters
+        assert_eq!(commands[0].0, "a=T,f=32,s=40,v=40,i=1,p=1,C=1,q=2,m=1");
+        assert_eq!(commands[1].0, "m=1");
+        assert_eq!(commands[2].0, "m=0");
+
+        let payload = commands
+            .iter()
+            .flat_map(|c| c.1.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(base64::decode(&payload).unwrap().len(), 40 * 40 * 4);
+    }
+
+    #[test]
+    fn frames_replace_the_same_image_and_placement() {
+        let mut target = KittyTarget::with_size(Vec::new(), 1, 1).image_id(7);
+        let first = parse(target.encode(&frame(1, 1)));
+        let second = parse(target.encode(&frame(1, 1)));
+
+        assert_eq!(first, second);
+        assert_eq!(first[0].0, "a=T,f=32,s=1,v=1,i=7,p=1,C=1,q=2,m=0");
+    }
+
+    #[test]
+    fn compressed_frames() {
+        let mut target = KittyTarget::with_size(Vec::new(), 2, 1).compress(true);
+        let commands = parse(target.encode(&frame(2, 1)));
+        assert_eq!(commands[0].0, "a=T,f=32,s=2,v=1,i=1,p=1,C=1,q=2,o=z,m=0");
+
+        let compressed = base64::decode(&commands[0].1).unwrap();
+        let mut rgba = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::ZlibDecoder::new(&compressed[..]),
+            &mut rgba,
+        )
+        .unwrap();
+        assert_eq!(rgba, [255, 127, 0, 255, 255, 127, 0, 255]);
+    }
+
+    #[test]
+    fn image_is_deleted_on_drop() {
+        let mut out = Vec::new();
+        drop(KittyTarget::with_size(&mut out, 1, 1).image_id(3));
+        assert_eq!(out, b"\x1B_Ga=d,d=I,i=3,q=2\x1B\\");
+    }
+}
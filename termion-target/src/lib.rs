@@ -1,30 +1,96 @@
-use std::io::{self, Write};
+use std::io::{Chain, Cursor, Read, Write};
 
-use termion::input::{Keys, TermRead};
-use termion::raw::{IntoRawMode, RawTerminal};
+use termion::input::{EventsAndRaw, TermReadEventsAndRaw};
 use termion::AsyncReader;
 use termishade::RenderTarget;
 
+mod cast;
+mod color;
+mod diff;
+mod encoder;
+mod event;
+mod iterm;
+mod kitty;
+mod quantize;
+mod query;
+mod sixel;
+mod terminal;
+
+pub use cast::{Cast, CastEvent, CastTarget, CastWriter};
+pub use color::{CellColor, ColorMode};
+use diff::Cell;
+pub use encoder::{Attrs, Encoder, Style};
+pub use event::{Event, MouseAction, MouseButton, MouseEvent};
+pub use iterm::{Dimension, ITermTarget};
+pub use kitty::KittyTarget;
+pub use sixel::SixelTarget;
+use terminal::Terminal;
 pub use termion::event::Key;
 
+const SGR_PIXELS_MOUSE_MODE: u16 = 1016;
+const SYNCHRONIZED_OUTPUT_MODE: u16 = 2026;
+const BEGIN_SYNCHRONIZED_UPDATE: &[u8] = b"\x1B[?2026h";
+const END_SYNCHRONIZED_UPDATE: &[u8] = b"\x1B[?2026l";
+
 pub struct TermionTarget {
     width: usize,
     height: usize,
-    reduced_palette: bool,
-    input: Option<Keys<AsyncReader>>,
-    raw: Option<RawTerminal<io::Stdout>>,
+    color_mode: ColorMode,
+    color_tolerance: f32,
+    pixel_aspect_ratio: Option<f32>,
+    front: Option<Vec<Cell>>,
+    back: Vec<Cell>,
+    encoder: Encoder,
+    synchronized_output: bool,
+    pixel_mouse: bool,
+    input: Option<EventsAndRaw<Chain<Cursor<Vec<u8>>, AsyncReader>>>,
+    terminal: Option<Terminal>,
 }
 
 impl TermionTarget {
     pub fn new() -> std::io::Result<Self> {
         let (w, h) = termion::terminal_size()?;
+        let terminal = Terminal::new()?;
+        let mut input = termion::async_stdin();
+        // keys pressed while querying the terminal
+        let mut pending = Vec::new();
+        let (color_mode, synchronized_output, pixel_mouse) = {
+            let mut screen = terminal.lock();
+            let color_mode = ColorMode::detect(&mut *screen);
+            let synchronized_output = query::supports_mode(
+                &mut *screen,
+                &mut input,
+                &mut pending,
+                SYNCHRONIZED_OUTPUT_MODE,
+            )
+            .unwrap_or(false);
+            let pixel_mouse = query::terminal_size_pixels().is_some()
+                && query::supports_mode(
+                    &mut *screen,
+                    &mut input,
+                    &mut pending,
+                    SGR_PIXELS_MOUSE_MODE,
+                )
+                .unwrap_or(false);
+            (color_mode, synchronized_output, pixel_mouse)
+        };
+        if pixel_mouse {
+            terminal.enable_pixel_mouse()?;
+        }
 
         Ok(Self {
             width: w as usize,
             height: h as usize,
-            reduced_palette: false,
-            input: Some(termion::async_stdin().keys()),
-            raw: Some(io::stdout().into_raw_mode()?),
+            color_mode,
+            color_tolerance: 0.0,
+            pixel_aspect_ratio: None,
+            front: None,
+            back: Vec::new(),
+            encoder: Encoder::new(),
+            synchronized_output,
+            pixel_mouse,
+            input: Some(Cursor::new(pending).chain(input).events_and_raw()),
+            terminal: Some(terminal),
         })
     }
 
@@ -32,67 +98,230 @@ impl TermionTarget {
         Self {
             width,
             height,
-            reduced_palette: false,
+            color_mode: ColorMode::TrueColor,
+            color_tolerance: 0.0,
+            pixel_aspect_ratio: None,
+            front: None,
+            back: Vec::new(),
+            encoder: Encoder::new(),
+            synchronized_output: false,
+            pixel_mouse: false,
             input: None,
-            raw: None,
+            terminal: None,
         }
     }
 
-    pub fn reduced_palette(mut self, reduced: bool) -> Self {
-        self.reduced_palette = reduced;
+    /// Overrides the automatically detected color mode.
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self.front = None;
         self
     }
 
-    pub fn get_key(&mut self) -> Option<Key> {
-        self.input.as_mut()?.next().and_then(Result::ok)
-    }
-    
-    pub fn draw_to_string(&self, buffer: &[nalgebra::Vector4<f32>]) -> String {
-        let mut cmd = String::new();
-        let mut prev_color = None;
-
-        for (row_num, row) in buffer.chunks(self.width).rev().step_by(2).enumerate() {
-            cmd += &format!("{}", termion::cursor::Goto(1, row_num as u16 + 1));
-            for pixel in row {
-                let pixel = pixel.map(|a| a.max(0.0).min(1.0));
-
-                if self.reduced_palette {
-                    let u8pixel = pixel.map(|a| (a * 5.0) as u8);
-                    if prev_color == Some(u8pixel) {
-                        cmd.push(' ');
-                        continue;
-                    }
-                    prev_color = Some(u8pixel);
-
-                    cmd += &format!(
-                        "{} ",
-                        termion::color::Bg(termion::color::AnsiValue::rgb(
-                            u8pixel.x,
-                            u8pixel.y,
-                            u8pixel.z
-                        ))
-                    );
-                } else {
-                    let u8pixel = pixel.map(|a| (a * 255.0) as u8);
-                    if prev_color == Some(u8pixel) {
-                        cmd.push(' ');
-                        continue;
+    /// Limits the colors to the 256 color palette instead of true color.
+    #[deprecated(note = "use `color_mode` instead")]
+    pub fn reduced_palette(self, reduced: bool) -> Self {
+        self.color_mode(if reduced {
+            ColorMode::Ansi256
+        } else {
+            ColorMode::TrueColor
+        })
+    }
+
+    /// Cells whose color changed by at most `tolerance` in every channel
+    /// since they were last drawn are not redrawn.
+    pub fn color_tolerance(mut self, tolerance: f32) -> Self {
+        self.color_tolerance = tolerance;
+        self
+    }
+
+    /// Overrides whether frames are wrapped in synchronized updates, which is
+    /// normally enabled if the terminal reports supporting them.
+    pub fn synchronized_output(mut self, enabled: bool) -> Self {
+        self.synchronized_output = enabled;
+        self
+    }
+
+    /// Overrides the aspect ratio of pixels, which is otherwise calculated
+    /// from the size of the terminal in pixels if it's known, or assumed to be 1.
+    pub fn with_pixel_aspect_ratio(mut self, ratio: f32) -> Self {
+        self.pixel_aspect_ratio = Some(ratio);
+        self
+    }
+
+    /// Forgets what is on the screen, so that the next frame is drawn in full.
+    pub fn invalidate(&mut self) {
+        self.front = None;
+        self.encoder.reset_state();
+    }
+
+    /// Sets the size in cells. This happens automatically in `get_event` and
+    /// `poll_resize` when the terminal is resized.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.invalidate();
+    }
+
+    /// Resizes the target if the size of the terminal has changed, and
+    /// returns the new size of the render target. `get_event` does this by
+    /// itself.
+    pub fn poll_resize(&mut self) -> Option<[usize; 2]> {
+        self.terminal.as_ref()?;
+
+        let (w, h) = termion::terminal_size().ok()?;
+        let (w, h) = (w as usize, h as usize);
+        if [w, h] == [self.width, self.height] {
+            return None;
+        }
+        self.resize(w, h);
+        Some(self.size())
+    }
+
+    pub fn get_event(&mut self) -> Option<Event> {
+        if let Some(size) = self.poll_resize() {
+            return Some(Event::Resize(size));
+        }
+        self.next_input()
+    }
+
+    fn next_input(&mut self) -> Option<Event> {
+        loop {
+            match self.input.as_mut()?.next()? {
+                Ok((termion::event::Event::Key(key), _)) => return Some(Event::Key(key)),
+                // termion doesn't understand all of the SGR reports, so all
+                // of them are parsed here
+                Ok((_, raw)) => {
+                    if let Some((action, pos)) = event::parse_sgr_mouse(&raw) {
+                        return Some(Event::Mouse(self.mouse_event(action, pos)));
                     }
-                    prev_color = Some(u8pixel);
-
-                    cmd += &format!(
-                        "{} ",
-                        termion::color::Bg(termion::color::Rgb(
-                            u8pixel.x,
-                            u8pixel.y,
-                            u8pixel.z
-                        ))
-                    );
                 }
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn mouse_event(&self, action: MouseAction, [x, y]: [usize; 2]) -> MouseEvent {
+        let (x, y) = (x.saturating_sub(1) as f32, y.saturating_sub(1) as f32);
+
+        // position in cells
+        let (fx, fy) = match query::terminal_size_pixels() {
+            Some([w, h]) if self.pixel_mouse => (
+                x / (w as f32 / self.width as f32),
+                y / (h as f32 / self.height as f32),
+            ),
+            _ => (x + 0.5, y + 0.5),
+        };
+
+        MouseEvent {
+            action,
+            cell: [
+                (fx as usize).min(self.width.saturating_sub(1)),
+                (fy as usize).min(self.height.saturating_sub(1)),
+            ],
+            pos: [fx, (self.height as f32 - fy) * 2.0],
+        }
+    }
+
+    /// Like `get_event`, but skips everything except keys. It doesn't check
+    /// for resizes, so callers that only use keys have to call `poll_resize`
+    /// before drawing and resize their renderers when it returns a size.
+    pub fn get_key(&mut self) -> Option<Key> {
+        loop {
+            if let Event::Key(key) = self.next_input()? {
+                return Some(key);
+            }
+        }
+    }
+
+    pub fn draw_to_string(&mut self, buffer: &[nalgebra::Vector4<f32>]) -> String {
+        // whatever the string is written to, the terminal state is unknown
+        self.encoder.reset_state();
+        self.encoder.clear();
+        self.fill_back(buffer);
+
+        for (row_num, row) in self.back.chunks(self.width).enumerate() {
+            self.encoder.move_to(0, row_num);
+            encode_cells(&mut self.encoder, row, true);
+        }
+
+        let s = String::from_utf8_lossy(self.encoder.bytes()).into_owned();
+        self.encoder.reset_state();
+        s
+    }
+
+    /// Like `draw_to_string`, but only emits the cells that changed since the
+    /// previous call.
+    pub fn draw_changes_to_string(&mut self, buffer: &[nalgebra::Vector4<f32>]) -> String {
+        self.encoder.clear();
+        self.encode_changes(buffer);
+        String::from_utf8_lossy(self.encoder.bytes()).into_owned()
+    }
+
+    fn encode_changes(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        self.fill_back(buffer);
+
+        let mut front = match self.front.take() {
+            Some(front) if front.len() == self.back.len() => front,
+            _ => {
+                self.encoder.reset_state();
+                for (row_num, row) in self.back.chunks(self.width).enumerate() {
+                    self.encoder.move_to(0, row_num);
+                    encode_cells(&mut self.encoder, row, true);
+                }
+                self.front = Some(self.back.clone());
+                return;
+            }
+        };
+
+        let rows = front
+            .chunks_mut(self.width)
+            .zip(self.back.chunks(self.width));
+        for (row_num, (front_row, row)) in rows.enumerate() {
+            for run in diff::changed_runs(front_row, row, self.color_tolerance) {
+                self.encoder.move_to(run.start, row_num);
+                encode_cells(&mut self.encoder, &row[run.clone()], run.end == self.width);
+                front_row[run.clone()].copy_from_slice(&row[run]);
             }
         }
 
-        cmd
+        self.front = Some(front);
+    }
+
+    fn fill_back(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        let color_mode = self.color_mode;
+        self.back.clear();
+        self.back.extend(
+            buffer
+                .chunks(self.width)
+                .rev()
+                .step_by(2)
+                .flatten()
+                .map(|&source| Cell {
+                    color: color_mode.quantize(source),
+                    source,
+                }),
+        );
+    }
+}
+
+/// Encodes a row segment as runs of blank cells of the same color.
+fn encode_cells(encoder: &mut Encoder, cells: &[Cell], reaches_end: bool) {
+    let mut rest = cells;
+    while let Some(first) = rest.first() {
+        let len = rest.iter().take_while(|c| c.color == first.color).count();
+
+        encoder.set_style(Style {
+            bg: Some(first.color),
+            ..Style::default()
+        });
+        if reaches_end && len == rest.len() {
+            encoder.blank_to_end(len);
+        } else {
+            encoder.blank(len);
+        }
+
+        rest = &rest[len..];
     }
 }
 
@@ -101,10 +330,64 @@ impl RenderTarget<nalgebra::Vector4<f32>> for TermionTarget {
         [self.width, self.height * 2]
     }
 
+    fn pixel_aspect_ratio(&self) -> f32 {
+        let detected = || {
+            self.terminal.as_ref()?;
+            let [w, h] = query::terminal_size_pixels()?;
+            // every cell is two pixels tall
+            let cell_width = w as f32 / self.width.max(1) as f32;
+            let cell_height = h as f32 / self.height.max(1) as f32;
+            Some(cell_width / (cell_height / 2.0))
+        };
+        self.pixel_aspect_ratio.or_else(detected).unwrap_or(1.0)
+    }
+
     fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
-        if self.raw.is_some() {
-            let s = self.draw_to_string(buffer);
-            write!(self.raw.as_mut().unwrap(), "{}", s).unwrap();
+        if self.terminal.is_none() {
+            return;
+        }
+
+        self.encoder.clear();
+        if self.synchronized_output {
+            self.encoder.write_raw(BEGIN_SYNCHRONIZED_UPDATE);
+        }
+        let prologue_len = self.encoder.bytes().len();
+
+        self.encode_changes(buffer);
+        if self.encoder.bytes().len() == prologue_len {
+            return;
         }
+
+        if self.synchronized_output {
+            self.encoder.write_raw(END_SYNCHRONIZED_UPDATE);
+        }
+
+        let mut screen = self.terminal.as_ref().unwrap().lock();
+        screen.write_all(self.encoder.bytes()).unwrap();
+        screen.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(deprecated)]
+    fn reduced_palette_sets_color_mode() {
+        let target = TermionTarget::new_without_io(1, 1).reduced_palette(true);
+        assert_eq!(target.color_mode, ColorMode::Ansi256);
+        let target = target.reduced_palette(false);
+        assert_eq!(target.color_mode, ColorMode::TrueColor);
+    }
+
+    #[test]
+    fn pixel_aspect_ratio() {
+        let target = TermionTarget::new_without_io(4, 2);
+        assert_eq!(target.pixel_aspect_ratio(), 1.0);
+        let target = target.with_pixel_aspect_ratio(0.5);
+        assert_eq!(target.pixel_aspect_ratio(), 0.5);
+        // two pixels per cell
+        assert_eq!(target.aspect_ratio(), 0.5);
     }
 }
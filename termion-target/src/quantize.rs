@@ -0,0 +1,135 @@
+use std::ops::Range;
+
+/// Reduces `pixels` to at most `max_colors` colors with median cut.
+///
+/// Returns the palette and the palette index of every pixel.
+pub fn median_cut(pixels: &[[u8; 3]], max_colors: usize) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let max_colors = max_colors.clamp(1, 256);
+    let mut order = (0..pixels.len() as u32).collect::<Vec<_>>();
+
+    let mut boxes = vec![ColorBox::new(pixels, &order, 0..order.len())];
+    while boxes.len() < max_colors {
+        let (i, widest) = match boxes.iter().enumerate().max_by_key(|(_, b)| b.extent) {
+            Some((i, b)) if b.extent > 0 => (i, b.clone()),
+            _ => break,
+        };
+
+        let axis = widest.axis;
+        let range = widest.range;
+        order[range.clone()].sort_unstable_by_key(|&p| pixels[p as usize][axis]);
+
+        // split at the median, but never between pixels of the same value,
+        // which would only produce duplicate colors
+        let key = |i: usize| pixels[order[i] as usize][axis];
+        let mut mid = (range.start + range.end) / 2;
+        while mid < range.end && key(mid) == key(mid - 1) {
+            mid += 1;
+        }
+        if mid == range.end {
+            mid = (range.start + range.end) / 2;
+            while key(mid) == key(mid - 1) {
+                mid -= 1;
+            }
+        }
+
+        boxes[i] = ColorBox::new(pixels, &order, range.start..mid);
+        boxes.push(ColorBox::new(pixels, &order, mid..range.end));
+    }
+
+    let mut palette = Vec::with_capacity(boxes.len());
+    let mut indices = vec![0; pixels.len()];
+    for (i, b) in boxes.iter().enumerate() {
+        let mut sum = [0u64; 3];
+        for &p in &order[b.range.clone()] {
+            for (s, c) in sum.iter_mut().zip(&pixels[p as usize]) {
+                *s += *c as u64;
+            }
+            indices[p as usize] = i as u8;
+        }
+
+        let len = b.range.len().max(1) as u64;
+        palette.push([
+            (sum[0] / len) as u8,
+            (sum[1] / len) as u8,
+            (sum[2] / len) as u8,
+        ]);
+    }
+
+    (palette, indices)
+}
+
+#[derive(Clone)]
+struct ColorBox {
+    range: Range<usize>,
+    // channel with the largest spread, and the spread itself
+    axis: usize,
+    extent: u8,
+}
+
+impl ColorBox {
+    fn new(pixels: &[[u8; 3]], order: &[u32], range: Range<usize>) -> Self {
+        let mut min = [u8::MAX; 3];
+        let mut max = [0; 3];
+        for &p in &order[range.clone()] {
+            for c in 0..3 {
+                min[c] = min[c].min(pixels[p as usize][c]);
+                max[c] = max[c].max(pixels[p as usize][c]);
+            }
+        }
+
+        let (axis, extent) = (0..3)
+            .map(|c| (c, max[c].saturating_sub(min[c])))
+            .max_by_key(|&(_, e)| e)
+            .unwrap();
+
+        // a single pixel can't be split any further
+        let extent = if range.len() > 1 { extent } else { 0 };
+
+        Self {
+            range,
+            axis,
+            extent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_size_is_limited() {
+        let pixels = (0..=255u8)
+            .map(|i| [i, i.wrapping_mul(7), 255 - i])
+            .collect::<Vec<_>>();
+        for &max in &[1, 2, 8, 100] {
+            let (palette, indices) = median_cut(&pixels, max);
+            assert_eq!(palette.len(), max);
+            assert_eq!(indices.len(), pixels.len());
+            assert!(indices.iter().all(|&i| (i as usize) < palette.len()));
+        }
+    }
+
+    #[test]
+    fn single_color() {
+        let (palette, indices) = median_cut(&[[10, 20, 30]; 10], 16);
+        assert_eq!(palette, [[10, 20, 30]]);
+        assert_eq!(indices, [0; 10]);
+    }
+
+    #[test]
+    fn fewer_colors_than_palette() {
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        let pixels = (0..30).map(|i| colors[i % 3]).collect::<Vec<_>>();
+        let (mut palette, indices) = median_cut(&pixels, 256);
+
+        // every color is kept exactly
+        for (pixel, &i) in pixels.iter().zip(&indices) {
+            assert_eq!(palette[i as usize], *pixel);
+        }
+        palette.sort();
+        let mut expected = colors.to_vec();
+        expected.sort();
+        assert_eq!(palette, expected);
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use termion::raw::CONTROL_SEQUENCE_TIMEOUT;
+
+/// Asks the terminal whether it supports the DEC private mode `mode` (DECRQM).
+///
+/// `out` should be in raw mode, otherwise the terminal's answer is echoed.
+/// Anything else read from `input` while waiting, like keystrokes, is
+/// appended to `pending`.
+pub fn supports_mode<W: Write, R: Read>(
+    out: &mut W,
+    input: &mut R,
+    pending: &mut Vec<u8>,
+    mode: u16,
+) -> io::Result<bool> {
+    write!(out, "\x1B[?{}$p", mode)?;
+    out.flush()?;
+
+    let timeout = Duration::from_millis(CONTROL_SEQUENCE_TIMEOUT);
+    let start = Instant::now();
+    let mut answer = Vec::new();
+    let mut buf = [0u8; 1];
+
+    // Either consume all data up to `$y` or wait for a timeout.
+    while !answer.ends_with(b"$y") && start.elapsed() < timeout {
+        if input.read(&mut buf)? > 0 {
+            answer.push(buf[0]);
+        } else {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    let status = split_reply(&mut answer, mode);
+    pending.extend(answer);
+    Ok(matches!(status, Some(b'1') | Some(b'2') | Some(b'3')))
+}
+
+/// Removes the answer to a DECRQM query from `bytes` and returns its status.
+///
+/// The answer will look like `ESC [ ? mode ; status $ y`, where status is 0 if
+/// the mode is unknown and 4 if it's permanently disabled.
+fn split_reply(bytes: &mut Vec<u8>, mode: u16) -> Option<u8> {
+    let prefix = format!("\x1B[?{};", mode);
+    let prefix = prefix.as_bytes();
+    let start = bytes.windows(prefix.len()).position(|w| w == prefix)?;
+    let reply = &bytes[start..];
+    let len = prefix.len() + 3;
+    if reply.len() < len || &reply[prefix.len() + 1..len] != b"$y" {
+        return None;
+    }
+
+    let status = reply[prefix.len()];
+    bytes.drain(start..start + len);
+    Some(status)
+}
+
+/// Size of the terminal in pixels, if it's known.
+pub fn terminal_size_pixels() -> Option<[usize; 2]> {
+    #[cfg(all(unix, not(target_os = "redox")))]
+    {
+        match termion::terminal_size_pixels() {
+            Ok((w, h)) if w > 0 && h > 0 => Some([w as usize, h as usize]),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "redox"))))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(input: &[u8], mode: u16) -> (bool, Vec<u8>) {
+        let mut out = Vec::new();
+        let mut pending = Vec::new();
+        let supported = supports_mode(&mut out, &mut &input[..], &mut pending, mode).unwrap();
+        assert_eq!(out, format!("\x1B[?{}$p", mode).as_bytes());
+        (supported, pending)
+    }
+
+    #[test]
+    fn status() {
+        assert_eq!(query(b"\x1B[?2026;1$y", 2026), (true, vec![]));
+        assert_eq!(query(b"\x1B[?2026;2$y", 2026), (true, vec![]));
+        assert_eq!(query(b"\x1B[?2026;0$y", 2026), (false, vec![]));
+        assert_eq!(query(b"\x1B[?2026;4$y", 2026), (false, vec![]));
+    }
+
+    #[test]
+    fn keystrokes_are_kept() {
+        assert_eq!(query(b"ay\x1B[?1016;1$y", 1016), (true, b"ay".to_vec()));
+        assert_eq!(
+            query(b"q\x1B[A\x1B[?1016;3$y", 1016),
+            (true, b"q\x1B[A".to_vec())
+        );
+    }
+
+    #[test]
+    fn no_reply() {
+        assert_eq!(query(b"hey", 2026), (false, b"hey".to_vec()));
+        // an answer about another mode isn't ours to take
+        assert_eq!(
+            query(b"\x1B[?1016;1$y", 2026),
+            (false, b"\x1B[?1016;1$y".to_vec())
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::{self, Write};
+
+use termishade::RenderTarget;
+
+use crate::quantize::median_cut;
+use crate::query;
+
+/// Draws frames as Sixel images at the top left of the terminal.
+///
+/// `out` is usually stdout in raw mode, possibly on the alternate screen.
+pub struct SixelTarget<W: Write> {
+    width: usize,
+    height: usize,
+    palette_size: usize,
+    out: W,
+    buf: Vec<u8>,
+    // sixel bits of every palette color in the current band
+    band: Vec<u8>,
+    used: Vec<bool>,
+}
+
+impl<W: Write> SixelTarget<W> {
+    /// Creates a target covering the terminal, except for the last row of
+    /// cells so that the image doesn't make it scroll.
+    pub fn new(out: W) -> io::Result<Self> {
+        let [w, h] = query::terminal_size_pixels()
+            .ok_or_else(|| io::Error::other("terminal size in pixels is unknown"))?;
+        let (_, rows) = termion::terminal_size()?;
+
+        let height = h - h / rows.max(1) as usize;
+        Ok(Self::with_size(out, w, height))
+    }
+
+    /// The height is rounded down to a multiple of 6, the height of a sixel band.
+    pub fn with_size(out: W, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height: height / 6 * 6,
+            palette_size: 256,
+            out,
+            buf: Vec::new(),
+            band: Vec::new(),
+            used: Vec::new(),
+        }
+    }
+
+    /// Maximum number of colors per frame, at most 256.
+    pub fn palette_size(mut self, size: usize) -> Self {
+        self.palette_size = size.clamp(2, 256);
+        self
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height / 6 * 6;
+    }
+
+    /// Encodes a frame as a Sixel image, preceded by moving the cursor home.
+    pub fn encode(&mut self, buffer: &[nalgebra::Vector4<f32>]) -> &[u8] {
+        let (width, height) = (self.width, self.height);
+        assert_eq!(buffer.len(), width * height);
+
+        // the color buffer starts at the bottom, the image starts at the top
+        let pixels = buffer
+            .chunks(width)
+            .rev()
+            .flatten()
+            .map(|p| {
+                let p = p.map(|a| (a.clamp(0.0, 1.0) * 255.0) as u8);
+                [p.x, p.y, p.z]
+            })
+            .collect::<Vec<_>>();
+        let (palette, indices) = median_cut(&pixels, self.palette_size);
+
+        self.buf.clear();
+        write!(self.buf, "\x1B[H\x1BPq\"1;1;{};{}", width, height).unwrap();
+        for (i, [r, g, b]) in palette.iter().enumerate() {
+            let percent = |c: u8| c as u32 * 100 / 255;
+            write!(
+                self.buf,
+                "#{};2;{};{};{}",
+                i,
+                percent(*r),
+                percent(*g),
+                percent(*b)
+            )
+            .unwrap();
+        }
+
+        self.band.clear();
+        self.band.resize(palette.len() * width, 0);
+        self.used.clear();
+        self.used.resize(palette.len(), false);
+
+        for (band_num, rows) in indices.chunks(width * 6).enumerate() {
+            if band_num > 0 {
+                self.buf.push(b'-');
+            }
+
+            for (bit, row) in rows.chunks(width).enumerate() {
+                for (x, &color) in row.iter().enumerate() {
+                    self.band[color as usize * width + x] |= 1 << bit;
+                    self.used[color as usize] = true;
+                }
+            }
+
+            let mut first = true;
+            for color in 0..palette.len() {
+                if !self.used[color] {
+                    continue;
+                }
+                self.used[color] = false;
+
+                if !first {
+                    self.buf.push(b'$');
+                }
+                first = false;
+
+                write!(self.buf, "#{}", color).unwrap();
+                let bits = &mut self.band[color * width..(color + 1) * width];
+                encode_sixels(&mut self.buf, bits);
+                bits.fill(0);
+            }
+        }
+
+        self.buf.extend_from_slice(b"\x1B\\");
+        &self.buf
+    }
+}
+
+/// Writes a line of sixels with run-length encoding, skipping trailing empty ones.
+fn encode_sixels(out: &mut Vec<u8>, bits: &[u8]) {
+    let len = bits.len() - bits.iter().rev().take_while(|&&b| b == 0).count();
+
+    let mut rest = &bits[..len];
+    while let Some(&first) = rest.first() {
+        let run = rest.iter().take_while(|&&b| b == first).count();
+        let c = b'?' + first;
+
+        // `!n` only pays off for runs longer than its own length
+        if run > 3 {
+            write!(out, "!{}", run).unwrap();
+            out.push(c);
+        } else {
+            out.resize(out.len() + run, c);
+        }
+
+        rest = &rest[run..];
+    }
+}
+
+impl<W: Write> RenderTarget<nalgebra::Vector4<f32>> for SixelTarget<W> {
+    fn size(&self) -> [usize; 2] {
+        [self.width, self.height]
+    }
+
+    fn draw(&mut self, buffer: &[nalgebra::Vector4<f32>]) {
+        self.encode(buffer);
+        self.out.write_all(&self.buf).unwrap();
+        self.out.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    fn sixels(bits: &[u8]) -> String {
+        let mut out = Vec::new();
+        encode_sixels(&mut out, bits);
+        String::from_utf8(out).unwrap()
+    }
+
+    // the sixel data between the palette and the string terminator
+    fn data(bytes: &[u8]) -> &str {
+        let text = std::str::from_utf8(bytes).unwrap();
+        let text = text.strip_suffix("\x1B\\").expect("no string terminator");
+        let start = text.rfind(';').unwrap();
+        let start = start + text[start..].find('#').unwrap();
+        &text[start..]
+    }
+
+    #[test]
+    fn run_length_encoding() {
+        assert_eq!(sixels(&[1, 1, 1]), "@@@");
+        assert_eq!(sixels(&[1, 1, 1, 1]), "!4@");
+        assert_eq!(sixels(&[0, 0, 63, 63, 63, 63, 63, 2]), "??!5~A");
+        // trailing empty sixels are skipped
+        assert_eq!(sixels(&[1, 0, 0, 0, 0, 0]), "@");
+        assert_eq!(sixels(&[0, 0]), "");
+    }
+
+    #[test]
+    fn single_band() {
+        let red = Vector4::new(1.0, 0.0, 0.0, 1.0);
+        let mut target = SixelTarget::with_size(Vec::new(), 2, 6);
+        assert_eq!(
+            target.encode(&[red; 12]),
+            &b"\x1B[H\x1BPq\"1;1;2;6#0;2;100;0;0#0~~\x1B\\"[..]
+        );
+    }
+
+    #[test]
+    fn bands_and_colors() {
+        let red = Vector4::new(1.0, 0.0, 0.0, 1.0);
+        let blue = Vector4::new(0.0, 0.0, 1.0, 1.0);
+        // the buffer starts at the bottom, so the top 3 rows are red
+        let mut buffer = vec![blue; 9];
+        buffer.extend(&[red; 3]);
+
+        let mut target = SixelTarget::with_size(Vec::new(), 1, 12);
+        let encoded = target.encode(&buffer).to_vec();
+        let text = String::from_utf8(encoded.clone()).unwrap();
+        let red_index = if text.contains("#0;2;100;0;0") { 0 } else { 1 };
+        let blue_index = 1 - red_index;
+
+        // every color of a band is separated by `$`, bands by `-`
+        assert_eq!(
+            data(&encoded),
+            format!("#{r}F$#{b}w-#{b}~", r = red_index, b = blue_index)
+        );
+    }
+
+    #[test]
+    fn height_is_a_multiple_of_six() {
+        let mut target = SixelTarget::with_size(Vec::new(), 3, 13);
+        assert_eq!(target.size(), [3, 12]);
+        target.resize(3, 5);
+        assert_eq!(target.size(), [3, 0]);
+    }
+
+    #[test]
+    fn palette_is_limited() {
+        let buffer = (0..36)
+            .map(|i| Vector4::new(i as f32 / 35.0, 0.0, 0.0, 1.0))
+            .collect::<Vec<_>>();
+        let mut target = SixelTarget::with_size(Vec::new(), 6, 6).palette_size(4);
+        let text = String::from_utf8(target.encode(&buffer).to_vec()).unwrap();
+        assert!(text.contains("#3;2;"));
+        assert!(!text.contains("#4;2;"));
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{self, Write};
+use std::panic;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use termion::input::MouseTerminal;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::{AlternateScreen, ToMainScreen};
+
+pub type Screen = MouseTerminal<AlternateScreen<RawTerminal<io::Stdout>>>;
+
+// `MouseTerminal` only reports movement while a button is held
+const ENABLE_ANY_MOTION: &str = "\x1B[?1003h";
+const DISABLE_ANY_MOTION: &str = "\x1B[?1003l";
+// same as what `MouseTerminal` sends on drop, plus any motion and pixel
+// coordinates
+const DISABLE_MOUSE: &str = "\x1B[?1016l\x1B[?1006l\x1B[?1015l\x1B[?1003l\x1B[?1002l\x1B[?1000l";
+
+/// Raw mode on the alternate screen with a hidden cursor and mouse reporting.
+///
+/// Everything is restored when this is dropped, and also when any thread
+/// panics, so that the panic message ends up readable on the main screen.
+pub struct Terminal {
+    screen: Arc<Mutex<Screen>>,
+}
+
+impl Terminal {
+    pub fn new() -> io::Result<Self> {
+        let screen = AlternateScreen::from(io::stdout().into_raw_mode()?);
+        let mut screen = MouseTerminal::from(screen);
+        write!(screen, "{}{}", ENABLE_ANY_MOTION, termion::cursor::Hide)?;
+        screen.flush()?;
+
+        let screen = Arc::new(Mutex::new(screen));
+
+        let weak = Arc::downgrade(&screen);
+        let prev_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(screen) = weak.upgrade() {
+                // if the panic happened while drawing, the lock is held and
+                // there's not much that can be done
+                if let Ok(mut screen) = screen.try_lock() {
+                    let _ = reset(&mut screen);
+                    let _ = write!(screen, "{}{}", DISABLE_MOUSE, ToMainScreen);
+                    let _ = screen.flush();
+                    let _ = screen.suspend_raw_mode();
+                }
+            }
+            prev_hook(info);
+        }));
+
+        Ok(Self { screen })
+    }
+
+    /// Switches mouse reports to pixel coordinates (SGR-Pixels).
+    pub fn enable_pixel_mouse(&self) -> io::Result<()> {
+        let mut screen = self.lock();
+        write!(screen, "\x1B[?1016h")?;
+        screen.flush()
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Screen> {
+        self.screen.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // mouse reporting, the alternate screen and raw mode are restored by
+        // their own destructors
+        let mut screen = self.lock();
+        let _ = write!(screen, "\x1B[?1016l{}", DISABLE_ANY_MOTION);
+        let _ = reset(&mut screen);
+    }
+}
+
+fn reset(screen: &mut Screen) -> io::Result<()> {
+    write!(screen, "{}{}", termion::style::Reset, termion::cursor::Show)?;
+    screen.flush()
+}
@@ -1,7 +1,27 @@
+use std::sync::OnceLock;
+
 pub trait BaseRenderer {
     type Color;
 
     fn size(&self) -> [usize; 2];
+    /// Reallocates the buffers for a new size. Their contents are unspecified afterwards.
+    ///
+    /// Panics by default, for renderers whose size is fixed.
+    fn resize(&mut self, size: [usize; 2]) {
+        panic!(
+            "this renderer can't be resized from {:?} to {:?}",
+            self.size(),
+            size
+        );
+    }
+
+    /// Offsets of the samples taken in every pixel from its center, in pixels.
+    /// The buffers hold every sample, with the samples of a pixel next to
+    /// each other. Defaults to a single sample at the center.
+    fn samples(&self) -> &[na::Vector2<f32>] {
+        static CENTER: OnceLock<[na::Vector2<f32>; 1]> = OnceLock::new();
+        CENTER.get_or_init(|| [na::Vector2::zeros()])
+    }
 
     fn color_buffer(&mut self) -> &mut [Self::Color];
     fn depth_buffer(&mut self) -> &mut [f32];
@@ -1,21 +1,78 @@
 use crate::base_renderer::BaseRenderer;
+use crate::sampling::resolve;
+use crate::util::flatten_coord;
+use crate::{ResolveFilter, SamplePattern};
 
 pub struct ColorDepthRenderer {
     width: usize,
     height: usize,
+    samples: Vec<na::Vector2<f32>>,
+    filter: ResolveFilter,
     color: Vec<na::Vector4<f32>>,
     depth: Vec<f32>,
+    resolved: Vec<na::Vector4<f32>>,
 }
 
 impl ColorDepthRenderer {
     pub fn new(width: usize, height: usize) -> Self {
+        Self::with_pattern(width, height, &SamplePattern::Grid([1, 1]))
+    }
+
+    /// Renders with multisample anti-aliasing, taking `level`x`level` samples
+    /// per pixel in a regular grid.
+    pub fn multisampled(width: usize, height: usize, level: u8) -> Self {
+        let level = level as usize;
+        Self::with_pattern(width, height, &SamplePattern::Grid([level, level]))
+    }
+
+    /// Renders with multisample anti-aliasing. Depth and color are stored for
+    /// every sample and coverage and depth are tested for every sample, but
+    /// the fragment shader only runs once per pixel for every triangle that
+    /// covers it. At most 64 samples per pixel are supported.
+    pub fn with_pattern(width: usize, height: usize, pattern: &SamplePattern) -> Self {
+        let samples = pattern.offsets();
+        assert!(
+            !samples.is_empty() && samples.len() <= 64,
+            "between 1 and 64 samples per pixel are supported"
+        );
+        let n = width * height * samples.len();
+
         Self {
             width,
             height,
-            color: vec![na::Vector4::zeros(); width * height],
-            depth: vec![0.0; width * height],
+            samples,
+            filter: ResolveFilter::Box,
+            color: vec![na::Vector4::zeros(); n],
+            depth: vec![0.0; n],
+            resolved: Vec::new(),
         }
     }
+
+    pub fn resolve_filter(mut self, filter: ResolveFilter) -> Self {
+        filter.validate();
+        self.filter = filter;
+        self
+    }
+
+    /// Combines the samples into pixels with the resolve filter. Without
+    /// multisampling this is the color buffer itself.
+    pub fn resolve(&mut self) -> &[na::Vector4<f32>] {
+        let num_samples = self.samples.len();
+        if num_samples == 1 {
+            return &self.color;
+        }
+
+        let size = self.size();
+        let color = &self.color;
+        resolve(
+            size,
+            &self.samples,
+            self.filter,
+            |pixel, i| color[flatten_coord(size, pixel) * num_samples + i],
+            &mut self.resolved,
+        );
+        &self.resolved
+    }
 }
 
 impl BaseRenderer for ColorDepthRenderer {
@@ -25,6 +82,18 @@ impl BaseRenderer for ColorDepthRenderer {
         [self.width, self.height]
     }
 
+    fn resize(&mut self, [width, height]: [usize; 2]) {
+        self.width = width;
+        self.height = height;
+        let n = width * height * self.samples.len();
+        self.color.resize(n, na::Vector4::zeros());
+        self.depth.resize(n, 0.0);
+    }
+
+    fn samples(&self) -> &[na::Vector2<f32>] {
+        &self.samples
+    }
+
     fn color_buffer(&mut self) -> &mut [Self::Color] {
         &mut self.color
     }
@@ -1,20 +1,83 @@
-use alga::general::ComplexField;
-use alga::linear::InnerSpace;
+pub use alga::general::SupersetOf;
+use alga::general::{Additive, ComplexField, Identity};
+pub use alga::linear::{FiniteDimVectorSpace, InnerSpace};
 
-fn triangle_area<V: InnerSpace + Copy>(ps: [V; 3]) -> V::RealField
+/// The space fields are interpolated in when `derive(Interpolate)` is given
+/// a scalar type other than the one of the renderer.
+pub type ScalarSpace<S> = na::Vector3<S>;
+
+/// Barycentric coordinates of `p` in the triangle `ps`.
+///
+/// They are ratios of signed areas, expressed with inner products so that
+/// they work in any dimension. Points outside of the triangle get negative
+/// weights, and points off its plane are projected onto it.
+pub fn barycentric<V>(ps: [V; 3], p: V) -> [V::RealField; 3]
 where
+    V: InnerSpace + Copy,
     V::RealField: From<f32>,
 {
     let [a, b, c] = ps;
     let ab = b - a;
-    let ac = b - c;
-    let angle = ab.angle(&ac);
-    return ab.norm() * ac.norm() * angle.sin() / V::RealField::from(2.0);
+    let ac = c - a;
+    let ap = p - a;
+
+    let dot = |x: &V, y: &V| x.inner_product(y).real();
+    let ab_ab = dot(&ab, &ab);
+    let ab_ac = dot(&ab, &ac);
+    let ac_ac = dot(&ac, &ac);
+    let ap_ab = dot(&ap, &ab);
+    let ap_ac = dot(&ap, &ac);
+
+    let full_area = ab_ab * ac_ac - ab_ac * ab_ac;
+    let b_area = (ac_ac * ap_ab - ab_ac * ap_ac) / full_area;
+    let c_area = (ab_ab * ap_ac - ab_ac * ap_ab) / full_area;
+    let a_area = V::RealField::from(1.0) - b_area - c_area;
+    [a_area, b_area, c_area]
+}
+
+/// Converts weights to another scalar type.
+pub fn convert_weights<R, S: SupersetOf<R>>(weights: [R; 3]) -> [S; 3] {
+    let [a, b, c] = weights;
+    [S::from_subset(&a), S::from_subset(&b), S::from_subset(&c)]
+}
+
+/// Barycentric coordinates corrected for perspective, given the coordinates
+/// in screen space and the `w` of every vertex in clip space.
+pub fn perspective_correct(linear: [f32; 3], w: [f32; 3]) -> [f32; 3] {
+    let [a, b, c] = [linear[0] / w[0], linear[1] / w[1], linear[2] / w[2]];
+    let sum = a + b + c;
+    [a / sum, b / sum, c / sum]
 }
 
 pub trait Interpolate3<V: InnerSpace>: Sized {
     fn to_barycentric(ps: [V; 3], p: V) -> [V::RealField; 3];
     fn interpolate(ps: [V; 3], p: V, v: [Self; 3]) -> Self;
+    /// Interpolates with known weights. `perspective` are corrected for
+    /// perspective, and `linear` are linear in screen space.
+    ///
+    /// By default this calls `interpolate` with the triangle spanned by the
+    /// first two basis vectors, at the point with `perspective` as weights.
+    fn interpolate_weights(
+        perspective: [V::RealField; 3],
+        _linear: [V::RealField; 3],
+        v: [Self; 3],
+    ) -> Self
+    where
+        V: FiniteDimVectorSpace + Copy,
+    {
+        assert!(V::dimension() >= 2, "weights need at least two dimensions");
+        let origin = <V as Identity<Additive>>::identity();
+        let mut p = origin;
+        p[0] = V::ComplexField::from_real(perspective[1]);
+        p[1] = V::ComplexField::from_real(perspective[2]);
+
+        let ps = [
+            origin,
+            V::canonical_basis_element(0),
+            V::canonical_basis_element(1),
+        ];
+        Self::interpolate(ps, p, v)
+    }
 }
 
 impl<T, V> Interpolate3<V> for T
@@ -24,21 +87,29 @@ where
     V::RealField: From<f32>,
 {
     fn to_barycentric(ps: [V; 3], p: V) -> [V::RealField; 3] {
-        let [a, b, c] = ps;
-        let full_area = triangle_area([a, b, c]);
-        let a_area = triangle_area([b, c, p]) / full_area;
-        let b_area = triangle_area([a, c, p]) / full_area;
-        let c_area = triangle_area([a, b, p]) / full_area;
-        [a_area, b_area, c_area]
+        barycentric(ps, p)
     }
 
     fn interpolate(ps: [V; 3], p: V, v: [Self; 3]) -> Self {
-        let [a, b, c] = v;
-        let [a_area, b_area, c_area] = Self::to_barycentric(ps, p);
-        a * a_area + b * b_area + c * c_area
+        weighted_sum(<Self as Interpolate3<V>>::to_barycentric(ps, p), v)
+    }
+
+    fn interpolate_weights(
+        perspective: [V::RealField; 3],
+        _: [V::RealField; 3],
+        v: [Self; 3],
+    ) -> Self {
+        weighted_sum(perspective, v)
     }
 }
 
+fn weighted_sum<T, W>([a_weight, b_weight, c_weight]: [W; 3], [a, b, c]: [T; 3]) -> T
+where
+    T: std::ops::Add<T, Output = T> + std::ops::Mul<W, Output = T>,
+{
+    a * a_weight + b * b_weight + c * c_weight
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Flat<T>(pub T);
 
@@ -60,4 +131,45 @@ where
     fn interpolate(_: [V; 3], _: V, [v, _, _]: [Self; 3]) -> Self {
         v
     }
+
+    fn interpolate_weights(
+        _: [V::RealField; 3],
+        _: [V::RealField; 3],
+        [v, _, _]: [Self; 3],
+    ) -> Self {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barycentric_is_exact_at_vertices_and_edges() {
+        let ps = [
+            na::Vector2::new(1.0, 2.0),
+            na::Vector2::new(9.0, 3.0),
+            na::Vector2::new(4.0, 8.0),
+        ];
+
+        assert_eq!(barycentric(ps, ps[0]), [1.0, 0.0, 0.0]);
+        assert_eq!(barycentric(ps, ps[1]), [0.0, 1.0, 0.0]);
+        assert_eq!(barycentric(ps, ps[2]), [0.0, 0.0, 1.0]);
+
+        assert_eq!(barycentric(ps, (ps[0] + ps[1]) / 2.0), [0.5, 0.5, 0.0]);
+        assert_eq!(barycentric(ps, (ps[1] + ps[2]) / 2.0), [0.0, 0.5, 0.5]);
+        assert_eq!(barycentric(ps, (ps[2] + ps[0]) / 2.0), [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn barycentric_in_3d() {
+        let ps = [
+            na::Vector3::new(0.0, 0.0, 0.0),
+            na::Vector3::new(4.0, 0.0, 4.0),
+            na::Vector3::new(0.0, 4.0, 0.0),
+        ];
+        let weights = barycentric(ps, na::Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(weights, [0.5, 0.25, 0.25]);
+    }
 }
@@ -10,8 +10,11 @@ pub mod interpolate;
 pub mod nalgebra_renderer;
 pub mod program;
 pub mod rasterizer;
+pub mod sampling;
 pub mod target;
+pub mod temporal;
 pub mod util;
+pub mod vertex_buffer;
 
 /// nalgebra extensions
 pub mod next;
@@ -22,6 +25,9 @@ pub use color_depth_renderer::ColorDepthRenderer;
 pub use interpolate::Interpolate3;
 #[cfg(feature = "na-renderer")]
 pub use nalgebra_renderer::*;
-pub use program::Program;
+pub use program::{InstancedProgram, Primitive, Program};
 pub use rasterizer::Rasterizer;
-pub use target::RenderTarget;
+pub use sampling::{ResolveFilter, SamplePattern};
+pub use target::{MemoryTarget, RenderTarget};
+pub use temporal::TemporalAntiAliasing;
+pub use vertex_buffer::{Format, FromVertex, VertexBuffer, VertexLayout, VertexSource};
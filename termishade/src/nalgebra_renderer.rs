@@ -1,8 +1,14 @@
+use crate::interpolate::{barycentric, perspective_correct};
+use crate::program::{assemble, Primitive};
 use crate::util::*;
-use crate::{base_renderer::BaseRenderer, Blender, Interpolate3, Program, Rasterizer};
+use crate::vertex_buffer::VertexSource;
+use crate::{
+    base_renderer::BaseRenderer, Blender, InstancedProgram, Interpolate3, Program, Rasterizer,
+};
 
 #[cfg(feature = "parallel")]
 use {
+    crate::program::{check_vertex_count, primitive_stage},
     parking_lot::Mutex,
     rayon::prelude::*,
 };
@@ -15,134 +21,353 @@ pub struct DrawParams<'a, P, R, B> {
 }
 
 pub trait NalgebraRenderer: BaseRenderer {
-    fn draw<P, R, B>(
+    fn draw<P, R, B, S>(&mut self, params: DrawParams<P, R, B>, vertices: &S, uniform: &P::Uniform)
+    where
+        P: Program<VertexOut = na::Vector4<f32>, ColorOut = Self::Color>,
+        R: Rasterizer<na::Vector2<f32>>,
+        B: Blender<Self::Color>,
+        P::Intermediate: Interpolate3<na::Vector3<f32>> + Copy,
+        S: VertexSource<P::VertexIn> + ?Sized,
+        Self::Color: Clone,
+        Self: Sized,
+    {
+        let transformed = (0..vertices.len())
+            .map(|i| vertices.with_vertex(i, |v| params.program.vertex(v, uniform)))
+            .collect::<Vec<_>>();
+        let primitives = assemble(params.program, &transformed, uniform);
+
+        rasterize(self, &params, &primitives, uniform);
+    }
+
+    /// Draws `vertices` once for every instance.
+    fn draw_instanced<P, R, B, S, I>(
         &mut self,
         params: DrawParams<P, R, B>,
-        vertices: &[P::VertexIn],
+        vertices: &S,
+        instances: &I,
         uniform: &P::Uniform,
     ) where
-        P: Program<VertexOut = na::Vector4<f32>, ColorOut = Self::Color>,
+        P: InstancedProgram<VertexOut = na::Vector4<f32>, ColorOut = Self::Color>,
         R: Rasterizer<na::Vector2<f32>>,
         B: Blender<Self::Color>,
         P::Intermediate: Interpolate3<na::Vector3<f32>> + Copy,
-        Self: Sized
+        S: VertexSource<P::VertexIn> + ?Sized,
+        I: VertexSource<P::Instance> + ?Sized,
+        Self::Color: Clone,
+        Self: Sized,
     {
-        let transformed = vertices
-            .into_iter()
-            .map(|v| params.program.vertex(v, uniform))
-            .collect::<Vec<_>>();
+        let mut transformed = Vec::with_capacity(vertices.len());
+        for index in 0..instances.len() {
+            transformed.clear();
+            instances.with_vertex(index, |instance| {
+                transformed.extend((0..vertices.len()).map(|i| {
+                    vertices.with_vertex(i, |v| {
+                        params.program.vertex_instanced(v, instance, index, uniform)
+                    })
+                }))
+            });
+            let primitives = assemble(params.program, &transformed, uniform);
 
-        let size = self.size();
-        for chunk in transformed.chunks(3) {
-            let vertices = [chunk[0].0, chunk[1].0, chunk[2].0];
-            let vertices = map(vertices, |v| v.xyz() / v.w);
-
-            let intermediate = [chunk[0].1, chunk[1].1, chunk[2].1];
+            rasterize(self, &params, &primitives, uniform);
+        }
+    }
+}
 
-            let screenspace_vertices = map(vertices, |v| to_screenspace(size, v.xy()));
-            let points = params.rasterizer.rasterize(&screenspace_vertices, size);
+impl<T> NalgebraRenderer for T where T: BaseRenderer<Color = na::Vector4<f32>> {}
 
-            for point in points {
-                let idx = flatten_coord(size, point);
-                let point = to_normspace(size, point);
+fn rasterize<T, P, R, B>(
+    renderer: &mut T,
+    params: &DrawParams<P, R, B>,
+    primitives: &[Primitive<P>],
+    uniform: &P::Uniform,
+) where
+    T: BaseRenderer + ?Sized,
+    T::Color: Clone,
+    P: Program<VertexOut = na::Vector4<f32>, ColorOut = T::Color>,
+    R: Rasterizer<na::Vector2<f32>>,
+    B: Blender<T::Color>,
+    P::Intermediate: Interpolate3<na::Vector3<f32>> + Copy,
+{
+    let size = renderer.size();
+    let samples = renderer.samples().to_vec();
+    for triangle in primitives {
+        let setup = Setup::new(size, triangle);
 
-                let z = <_>::interpolate(map(vertices, |v| v.xy()), point, map(vertices, |v| v.z));
+        for (pixel, mask) in setup.coverage(params.rasterizer, size, &samples) {
+            let first = flatten_coord(size, pixel) * samples.len();
 
+            let mut passed = 0u64;
+            for i in sample_indices(mask) {
+                let z = setup.depth(pixel, samples[i]);
                 if z < 0.0 {
                     continue;
                 }
                 if params.depth_test_enabled {
-                    let depth = self.depth_buffer();
-                    if depth[idx] < z {
+                    let depth = &mut renderer.depth_buffer()[first + i];
+                    if *depth < z {
                         continue;
                     }
-                    depth[idx] = z;
+                    *depth = z;
                 }
+                passed |= 1 << i;
+            }
 
-                let point = na::Vector4::new(point.x, point.y, z, 1.0);
-                let intermediate = <_>::interpolate(vertices, point.xyz(), intermediate);
-                let src = params.program.fragment(&point, &intermediate, uniform);
-                let color = self.color_buffer();
-                color[idx] = params.blender.blend(&color[idx], src);
+            if passed == 0 {
+                continue;
+            }
+
+            let (point, intermediate) = setup.fragment_input(size, pixel);
+            let src = params.program.fragment(&point, &intermediate, uniform);
+            let color = renderer.color_buffer();
+            for i in sample_indices(passed) {
+                color[first + i] = params.blender.blend(&color[first + i], src.clone());
             }
         }
     }
 }
 
-impl<T> NalgebraRenderer for T where T: BaseRenderer<Color = na::Vector4<f32>> {}
+// a triangle after the perspective divide, shared by both renderers
+struct Setup<I> {
+    vertices: [na::Vector3<f32>; 3],
+    screenspace: [na::Vector2<f32>; 3],
+    ws: [f32; 3],
+    intermediate: [I; 3],
+}
+
+impl<I> Setup<I>
+where
+    I: Interpolate3<na::Vector3<f32>> + Copy,
+{
+    fn new(size: [usize; 2], triangle: &[(na::Vector4<f32>, I); 3]) -> Self {
+        let vertices = map(*triangle, |v| v.0);
+        let ws = map(vertices, |v| v.w);
+        let vertices = map(vertices, |v| v.xyz() / v.w);
+
+        Setup {
+            vertices,
+            screenspace: map(vertices, |v| to_screenspace(size, v.xy())),
+            ws,
+            intermediate: map(*triangle, |v| v.1),
+        }
+    }
+
+    // covered pixels with a mask of their covered samples
+    fn coverage<R>(
+        &self,
+        rasterizer: &R,
+        size: [usize; 2],
+        samples: &[na::Vector2<f32>],
+    ) -> Vec<([usize; 2], u64)>
+    where
+        R: Rasterizer<na::Vector2<f32>> + ?Sized,
+    {
+        if let [center] = samples {
+            if *center == na::Vector2::zeros() {
+                return rasterizer
+                    .rasterize(&self.screenspace, size)
+                    .into_iter()
+                    .map(|pixel| (pixel, 1))
+                    .collect();
+            }
+        }
+        rasterizer.rasterize_samples(&self.screenspace, size, samples)
+    }
+
+    // depth at the sample `offset` from the center of `pixel`
+    fn depth(&self, pixel: [usize; 2], offset: na::Vector2<f32>) -> f32 {
+        let center = na::Vector2::new(pixel[0] as f32, pixel[1] as f32);
+        self.depth_at(barycentric(self.screenspace, center + offset))
+    }
+
+    fn depth_at(&self, weights: [f32; 3]) -> f32 {
+        weights[0] * self.vertices[0].z
+            + weights[1] * self.vertices[1].z
+            + weights[2] * self.vertices[2].z
+    }
+
+    // the fragment shader inputs at the center of `pixel`
+    fn fragment_input(&self, size: [usize; 2], pixel: [usize; 2]) -> (na::Vector4<f32>, I) {
+        let point = to_normspace(size, pixel);
+        let weights = barycentric(map(self.vertices, |v| v.xy()), point);
+        let z = self.depth_at(weights);
+        let intermediate = <_>::interpolate_weights(
+            perspective_correct(weights, self.ws),
+            weights,
+            self.intermediate,
+        );
+        (na::Vector4::new(point.x, point.y, z, 1.0), intermediate)
+    }
+}
+
+fn sample_indices(mask: u64) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |i| mask & 1 << i != 0)
+}
 
 #[cfg(feature = "parallel")]
 pub trait NalgebraParRenderer: NalgebraRenderer {
-    fn draw<P, R, B>(
-        &mut self,
-        params: DrawParams<P, R, B>,
-        vertices: &[P::VertexIn],
-        uniform: &P::Uniform,
-    ) where
+    fn draw<P, R, B, S>(&mut self, params: DrawParams<P, R, B>, vertices: &S, uniform: &P::Uniform)
+    where
         P: Program<VertexOut = na::Vector4<f32>, ColorOut = Self::Color> + Sync,
         R: Rasterizer<na::Vector2<f32>> + Sync,
         B: Blender<Self::Color> + Sync,
         P::Intermediate: Interpolate3<na::Vector3<f32>> + Copy + Send + Sync,
         P::Uniform: Sync,
-        P::VertexIn: Sync,
+        S: VertexSource<P::VertexIn> + Sync + ?Sized,
         Self::Color: Clone + Send + Sync,
     {
-        let transformed = vertices
+        let transformed = (0..vertices.len())
             .into_par_iter()
-            .map(|v| params.program.vertex(v, uniform))
+            .map(|i| vertices.with_vertex(i, |v| params.program.vertex(v, uniform)))
             .collect::<Vec<_>>();
-
-        let buffer = (0..self.color_buffer().len())
-            .map(|i| Mutex::new((self.color_buffer()[i].clone(), self.depth_buffer()[i])))
+        check_vertex_count(transformed.len());
+        let primitives = transformed
+            .par_chunks_exact(3)
+            .flat_map_iter(|triangle| {
+                let mut out = Vec::new();
+                primitive_stage(params.program, triangle, uniform, &mut out);
+                out
+            })
             .collect::<Vec<_>>();
 
+        let buffer = lock_buffers(self);
+        let samples = self.samples().to_vec();
+        par_rasterize(
+            &buffer,
+            self.size(),
+            &samples,
+            &params,
+            &primitives,
+            uniform,
+        );
+        unlock_buffers(self, buffer);
+    }
+
+    /// Draws `vertices` once for every instance, with instances spread
+    /// across threads.
+    fn draw_instanced<P, R, B, S, I>(
+        &mut self,
+        params: DrawParams<P, R, B>,
+        vertices: &S,
+        instances: &I,
+        uniform: &P::Uniform,
+    ) where
+        P: InstancedProgram<VertexOut = na::Vector4<f32>, ColorOut = Self::Color> + Sync,
+        R: Rasterizer<na::Vector2<f32>> + Sync,
+        B: Blender<Self::Color> + Sync,
+        P::Intermediate: Interpolate3<na::Vector3<f32>> + Copy + Send + Sync,
+        P::Uniform: Sync,
+        S: VertexSource<P::VertexIn> + Sync + ?Sized,
+        I: VertexSource<P::Instance> + Sync + ?Sized,
+        Self::Color: Clone + Send + Sync,
+    {
+        let buffer = lock_buffers(self);
         let size = self.size();
-        transformed.par_chunks(3).for_each(|chunk| {
-            let vertices = [chunk[0].0, chunk[1].0, chunk[2].0];
-            let vertices = map(vertices, |v| v.xyz() / v.w);
+        let samples = self.samples().to_vec();
 
-            let intermediate = [chunk[0].1, chunk[1].1, chunk[2].1];
+        (0..instances.len()).into_par_iter().for_each(|index| {
+            let transformed = instances.with_vertex(index, |instance| {
+                (0..vertices.len())
+                    .map(|i| {
+                        vertices.with_vertex(i, |v| {
+                            params.program.vertex_instanced(v, instance, index, uniform)
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            });
+            let primitives = assemble(params.program, &transformed, uniform);
 
-            let screenspace_vertices = map(vertices, |v| to_screenspace(size, v.xy()));
-            params
-                .rasterizer
-                .rasterize(&screenspace_vertices, size)
-                .into_par_iter()
-                .for_each(|point| {
-                    let idx = flatten_coord(size, point);
-                    let point = to_normspace(size, point);
+            par_rasterize(&buffer, size, &samples, &params, &primitives, uniform);
+        });
 
-                    let z =
-                        <_>::interpolate(map(vertices, |v| v.xy()), point, map(vertices, |v| v.z));
+        unlock_buffers(self, buffer);
+    }
+}
 
-                    let cell = &buffer[idx];
+#[cfg(feature = "parallel")]
+impl<T> NalgebraParRenderer for T where T: NalgebraRenderer {}
 
-                    if z < 0.0 {
-                        return;
-                    }
+#[cfg(feature = "parallel")]
+type Cell<C> = Mutex<(C, f32)>;
+
+#[cfg(feature = "parallel")]
+fn lock_buffers<T>(renderer: &mut T) -> Vec<Cell<T::Color>>
+where
+    T: BaseRenderer + ?Sized,
+    T::Color: Clone,
+{
+    (0..renderer.color_buffer().len())
+        .map(|i| {
+            Mutex::new((
+                renderer.color_buffer()[i].clone(),
+                renderer.depth_buffer()[i],
+            ))
+        })
+        .collect()
+}
+
+#[cfg(feature = "parallel")]
+fn unlock_buffers<T: BaseRenderer + ?Sized>(renderer: &mut T, buffer: Vec<Cell<T::Color>>) {
+    buffer.into_iter().enumerate().for_each(|(i, cell)| {
+        let (color, depth) = cell.into_inner();
+        renderer.color_buffer()[i] = color;
+        renderer.depth_buffer()[i] = depth;
+    });
+}
+
+#[cfg(feature = "parallel")]
+fn par_rasterize<C, P, R, B>(
+    buffer: &[Cell<C>],
+    size: [usize; 2],
+    samples: &[na::Vector2<f32>],
+    params: &DrawParams<P, R, B>,
+    primitives: &[Primitive<P>],
+    uniform: &P::Uniform,
+) where
+    C: Clone + Send,
+    P: Program<VertexOut = na::Vector4<f32>, ColorOut = C> + Sync,
+    R: Rasterizer<na::Vector2<f32>> + Sync,
+    B: Blender<C> + Sync,
+    P::Intermediate: Interpolate3<na::Vector3<f32>> + Copy + Send + Sync,
+    P::Uniform: Sync,
+{
+    let first_sample = |pixel| flatten_coord(size, pixel) * samples.len();
+    primitives.par_iter().for_each(|triangle| {
+        let setup = Setup::new(size, triangle);
+
+        setup
+            .coverage(params.rasterizer, size, samples)
+            .into_par_iter()
+            .for_each(|(pixel, mask)| {
+                let first = first_sample(pixel);
+                let depth_test =
+                    |i: usize, z: f32| !params.depth_test_enabled || z <= buffer[i].lock().1;
+
+                // samples that may pass, to skip the fragment shader if
+                // none of them do
+                let candidates = sample_indices(mask)
+                    .map(|i| (i, setup.depth(pixel, samples[i])))
+                    .filter(|&(i, z)| z >= 0.0 && depth_test(first + i, z))
+                    .collect::<Vec<_>>();
+                if candidates.is_empty() {
+                    return;
+                }
+
+                let (point, intermediate) = setup.fragment_input(size, pixel);
+                let src = params.program.fragment(&point, &intermediate, uniform);
+
+                // another triangle may have been drawn in front in the
+                // meantime, so the depth is tested again under the same
+                // lock as the writes
+                for (i, z) in candidates {
+                    let mut lock = buffer[first + i].lock();
                     if params.depth_test_enabled {
-                        let mut lock = cell.lock();
                         if lock.1 < z {
-                            return;
+                            continue;
                         }
                         lock.1 = z;
                     }
-
-                    let point = na::Vector4::new(point.x, point.y, z, 1.0);
-                    let intermediate = <_>::interpolate(vertices, point.xyz(), intermediate);
-                    let src = params.program.fragment(&point, &intermediate, uniform);
-                    let mut lock = cell.lock();
-                    lock.0 = params.blender.blend(&lock.0, src);
-                });
-        });
-
-        buffer.into_iter().enumerate().for_each(|(i, cell)| {
-            let (color, depth) = cell.into_inner();
-            self.color_buffer()[i] = color;
-            self.depth_buffer()[i] = depth;
-        });
-    }
+                    lock.0 = params.blender.blend(&lock.0, src.clone());
+                }
+            });
+    });
 }
-
-#[cfg(feature = "parallel")]
-impl<T> NalgebraParRenderer for T where T: NalgebraRenderer {}
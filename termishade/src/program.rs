@@ -1,5 +1,9 @@
 use alga::linear::InnerSpace;
 
+/// A triangle after the vertex stage: a position and an intermediate for
+/// every vertex.
+pub type Primitive<P> = [(<P as Program>::VertexOut, <P as Program>::Intermediate); 3];
+
 pub trait Program: Sync {
     type VertexIn;
     type VertexOut: InnerSpace;
@@ -18,4 +22,80 @@ pub trait Program: Sync {
         i: &Self::Intermediate,
         u: &Self::Uniform,
     ) -> Self::ColorOut;
+
+    /// Runs between the vertex stage and rasterization for every triangle.
+    /// The triangles pushed to `out` are rasterized in its place, so it can
+    /// drop, modify or add triangles. Passes it through by default.
+    ///
+    /// Only triangles are supported, and without adjacency: lines and points
+    /// have to be emitted as thin triangles, and outlines like silhouettes
+    /// need the neighbouring triangles passed in the uniform.
+    fn primitive(
+        &self,
+        triangle: Primitive<Self>,
+        _: &Self::Uniform,
+        out: &mut Vec<Primitive<Self>>,
+    ) {
+        out.push(triangle);
+    }
+}
+
+/// Groups transformed vertices into triangles and runs the primitive stage.
+#[cfg(feature = "na-renderer")]
+pub(crate) fn assemble<P>(
+    program: &P,
+    transformed: &[(P::VertexOut, P::Intermediate)],
+    uniform: &P::Uniform,
+) -> Vec<Primitive<P>>
+where
+    P: Program,
+    P::VertexOut: Copy,
+    P::Intermediate: Copy,
+{
+    check_vertex_count(transformed.len());
+    let mut out = Vec::with_capacity(transformed.len() / 3);
+    for triangle in transformed.chunks_exact(3) {
+        primitive_stage(program, triangle, uniform, &mut out);
+    }
+    out
+}
+
+#[cfg(feature = "na-renderer")]
+pub(crate) fn check_vertex_count(len: usize) {
+    assert!(
+        len.is_multiple_of(3),
+        "vertex count must be a multiple of 3, got {}",
+        len
+    );
+}
+
+/// Runs the primitive stage for the three vertices of one triangle.
+#[cfg(feature = "na-renderer")]
+pub(crate) fn primitive_stage<P>(
+    program: &P,
+    triangle: &[(P::VertexOut, P::Intermediate)],
+    uniform: &P::Uniform,
+    out: &mut Vec<Primitive<P>>,
+) where
+    P: Program,
+    P::VertexOut: Copy,
+    P::Intermediate: Copy,
+{
+    debug_assert_eq!(triangle.len(), 3);
+    program.primitive([triangle[0], triangle[1], triangle[2]], uniform, out);
+}
+
+/// Programs that can draw the same vertices many times in one call, with
+/// different data for every instance.
+pub trait InstancedProgram: Program {
+    type Instance;
+
+    /// Like `Program::vertex`, with the data and the index of the instance.
+    fn vertex_instanced(
+        &self,
+        v: &Self::VertexIn,
+        instance: &Self::Instance,
+        index: usize,
+        u: &Self::Uniform,
+    ) -> (Self::VertexOut, Self::Intermediate);
 }
@@ -1,7 +1,40 @@
 use crate::util::*;
+use std::collections::BTreeMap;
+use std::ops::Sub;
 
 pub trait Rasterizer<V> {
     fn rasterize(&self, vertices: &[V; 3], size: [usize; 2]) -> Vec<[usize; 2]>;
+
+    /// Finds the samples covered by a triangle, for multisample anti-aliasing.
+    /// `samples` are offsets from the centers of pixels, at most 64 of them.
+    /// Returns the covered pixels, each with a mask of its covered samples.
+    ///
+    /// By default the triangle is rasterized once for every sample, moved so
+    /// that the sample lands on the pixel centers.
+    fn rasterize_samples(
+        &self,
+        vertices: &[V; 3],
+        size: [usize; 2],
+        samples: &[V],
+    ) -> Vec<([usize; 2], u64)>
+    where
+        V: Copy + Sub<Output = V>,
+    {
+        assert!(samples.len() <= 64);
+
+        let mut masks = BTreeMap::new();
+        for (i, &offset) in samples.iter().enumerate() {
+            let moved = [
+                vertices[0] - offset,
+                vertices[1] - offset,
+                vertices[2] - offset,
+            ];
+            for pixel in self.rasterize(&moved, size) {
+                *masks.entry(pixel).or_insert(0) |= 1 << i;
+            }
+        }
+        masks.into_iter().collect()
+    }
 }
 
 pub struct TriangleRasterizer;
@@ -19,4 +52,49 @@ impl Rasterizer<na::Vector2<f32>> for TriangleRasterizer {
             Vec::new()
         }
     }
+
+    // tests every sample against the edges directly instead of rasterizing
+    // once per sample
+    fn rasterize_samples(
+        &self,
+        vertices: &[na::Vector2<f32>; 3],
+        [width, height]: [usize; 2],
+        samples: &[na::Vector2<f32>],
+    ) -> Vec<([usize; 2], u64)> {
+        assert!(samples.len() <= 64);
+
+        let [a, b, c] = *vertices;
+        if edge(a, b, c) == 0.0 || width == 0 || height == 0 {
+            return Vec::new();
+        }
+
+        // samples are within half a pixel from the center
+        let min = a.inf(&b).inf(&c).add_scalar(-0.5).map(f32::ceil);
+        let max = a.sup(&b).sup(&c).add_scalar(0.5).map(f32::floor);
+        if max.x < 0.0 || max.y < 0.0 || min.x > (width - 1) as f32 || min.y > (height - 1) as f32 {
+            return Vec::new();
+        }
+        let [sx, sy] = [min.x.max(0.0) as usize, min.y.max(0.0) as usize];
+        let [ex, ey] = [
+            (max.x as usize).min(width - 1),
+            (max.y as usize).min(height - 1),
+        ];
+
+        iproduct!(sx..=ex, sy..=ey)
+            .filter_map(|(x, y)| {
+                let center = na::Vector2::new(x as f32, y as f32);
+                let mask = samples
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &offset)| is_point_inside_triangle(vertices, center + offset))
+                    .fold(0, |mask, (i, _)| mask | 1 << i);
+
+                if mask != 0 {
+                    Some(([x, y], mask))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
 }
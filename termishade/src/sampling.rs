@@ -0,0 +1,217 @@
+use alga::linear::VectorSpace;
+
+/// Positions of the samples taken in every pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SamplePattern {
+    /// `[x, y]` samples along each axis in a regular grid, e.g. `[2, 4]` for
+    /// terminal cells that are twice as tall as they are wide.
+    Grid([usize; 2]),
+    /// `n`x`n` samples on a grid rotated so that no two of them share a row
+    /// or a column, which handles nearly horizontal and vertical edges better.
+    RotatedGrid(usize),
+    /// Offsets from the center of the pixel, within half a pixel.
+    Custom(Vec<na::Vector2<f32>>),
+}
+
+impl SamplePattern {
+    /// Offsets of the samples from the center of the pixel, in pixels.
+    pub fn offsets(&self) -> Vec<na::Vector2<f32>> {
+        match self {
+            SamplePattern::Grid([sx, sy]) => {
+                let [sx, sy] = [(*sx).max(1), (*sy).max(1)];
+                iproduct!(0..sy, 0..sx)
+                    .map(|(y, x)| {
+                        na::Vector2::new(
+                            (x as f32 + 0.5) / sx as f32 - 0.5,
+                            (y as f32 + 0.5) / sy as f32 - 0.5,
+                        )
+                    })
+                    .collect()
+            }
+            SamplePattern::RotatedGrid(n) => {
+                let n = (*n).max(1);
+                let count = (n * n) as f32;
+                // the lattice spanned by (n, -1) and (1, n) on an n²xn² grid
+                iproduct!(0..n, 0..n)
+                    .map(|(i, j)| {
+                        let x = i * n + j;
+                        let y = j * n + (n - 1 - i);
+                        na::Vector2::new(
+                            (x as f32 + 0.5) / count - 0.5,
+                            (y as f32 + 0.5) / count - 0.5,
+                        )
+                    })
+                    .collect()
+            }
+            SamplePattern::Custom(offsets) => offsets.clone(),
+        }
+    }
+}
+
+/// How samples are weighed when they are combined into pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResolveFilter {
+    /// Averages the samples of each pixel.
+    Box,
+    /// Weighs samples linearly by distance, up to a pixel away.
+    Tent,
+    /// Gaussian with the given standard deviation in pixels, which must be
+    /// positive.
+    Gaussian(f32),
+    /// Windowed sinc with the given number of lobes, at least one. Sharper
+    /// than the others, but may cause ringing around edges.
+    Lanczos(u8),
+}
+
+impl ResolveFilter {
+    /// Panics if the parameter of the filter is out of range.
+    pub fn validate(self) {
+        match self {
+            ResolveFilter::Gaussian(sigma) => assert!(
+                sigma > 0.0 && sigma.is_finite(),
+                "gaussian sigma must be positive and finite, got {}",
+                sigma
+            ),
+            ResolveFilter::Lanczos(lobes) => {
+                assert!(lobes > 0, "lanczos filter needs at least one lobe")
+            }
+            ResolveFilter::Box | ResolveFilter::Tent => {}
+        }
+    }
+
+    /// Distance in pixels beyond which samples have no weight.
+    pub fn radius(self) -> f32 {
+        self.validate();
+        match self {
+            ResolveFilter::Box => 0.5,
+            ResolveFilter::Tent => 1.0,
+            ResolveFilter::Gaussian(sigma) => sigma * 3.0,
+            ResolveFilter::Lanczos(lobes) => lobes as f32,
+        }
+    }
+
+    /// Weight of a sample at `offset` from the center of the pixel.
+    pub fn weight(self, offset: na::Vector2<f32>) -> f32 {
+        self.weight_1d(offset.x) * self.weight_1d(offset.y)
+    }
+
+    fn weight_1d(self, x: f32) -> f32 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+
+        match self {
+            // half-open so that samples on the border belong to one pixel
+            ResolveFilter::Box if x == 0.5 => 0.0,
+            ResolveFilter::Box => 1.0,
+            ResolveFilter::Tent => 1.0 - x,
+            ResolveFilter::Gaussian(sigma) => (-x * x / (2.0 * sigma * sigma)).exp(),
+            ResolveFilter::Lanczos(lobes) => sinc(x) * sinc(x / lobes as f32),
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// Combines samples into pixels. `sample(pixel, i)` is the sample of `pixel`
+/// at `offsets[i]` from its center.
+pub fn resolve<Color, F>(
+    [width, height]: [usize; 2],
+    offsets: &[na::Vector2<f32>],
+    filter: ResolveFilter,
+    sample: F,
+    out: &mut Vec<Color>,
+) where
+    Color: VectorSpace,
+    Color::Field: From<f32>,
+    F: Fn([usize; 2], usize) -> Color,
+{
+    filter.validate();
+
+    // samples of pixels up to this far away can have weight
+    let reach = (filter.radius() + 0.5).floor() as isize;
+
+    // the weights are the same for every pixel
+    let weights = iproduct!(-reach..=reach, -reach..=reach, 0..offsets.len())
+        .filter_map(|(dy, dx, i)| {
+            let offset = na::Vector2::new(dx as f32, dy as f32) + offsets[i];
+            let weight = filter.weight(offset);
+            if weight != 0.0 {
+                Some(([dx, dy], i, weight))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    out.clear();
+    out.extend(iproduct!(0..height, 0..width).map(|(y, x)| {
+        let mut sum = na::zero::<Color>();
+        let mut total = 0.0;
+        for &([dx, dy], i, weight) in &weights {
+            let (sx, sy) = (x as isize + dx, y as isize + dy);
+            if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize {
+                continue;
+            }
+            sum += sample([sx as usize, sy as usize], i) * Color::Field::from(weight);
+            total += weight;
+        }
+
+        if total > 0.0 {
+            sum * Color::Field::from(total.recip())
+        } else {
+            sum
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights() {
+        let center = na::Vector2::zeros();
+        assert_eq!(ResolveFilter::Box.weight(center), 1.0);
+        assert_eq!(ResolveFilter::Box.weight(na::Vector2::new(0.5, 0.0)), 0.0);
+        assert_eq!(ResolveFilter::Tent.weight(na::Vector2::new(0.5, 0.5)), 0.25);
+        assert_eq!(ResolveFilter::Gaussian(0.5).weight(center), 1.0);
+        assert_eq!(ResolveFilter::Lanczos(2).weight(center), 1.0);
+        assert_eq!(
+            ResolveFilter::Lanczos(2).weight(na::Vector2::new(2.5, 0.0)),
+            0.0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "gaussian sigma must be positive")]
+    fn zero_sigma() {
+        ResolveFilter::Gaussian(0.0).weight(na::Vector2::zeros());
+    }
+
+    #[test]
+    #[should_panic(expected = "gaussian sigma must be positive")]
+    fn negative_sigma() {
+        ResolveFilter::Gaussian(-1.0).radius();
+    }
+
+    #[test]
+    #[should_panic(expected = "gaussian sigma must be positive")]
+    fn nan_sigma() {
+        ResolveFilter::Gaussian(f32::NAN).radius();
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one lobe")]
+    fn zero_lobes() {
+        ResolveFilter::Lanczos(0).weight(na::Vector2::zeros());
+    }
+}
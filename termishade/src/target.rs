@@ -1,3 +1,4 @@
+use crate::sampling::{resolve, ResolveFilter, SamplePattern};
 use crate::util::flatten_coord;
 
 use alga::linear::VectorSpace;
@@ -6,6 +7,18 @@ pub trait RenderTarget<Color: 'static> {
     fn size(&self) -> [usize; 2];
     fn draw(&mut self, data: &[Color]);
 
+    /// Width of a pixel divided by its height, as it is shown.
+    fn pixel_aspect_ratio(&self) -> f32 {
+        1.0
+    }
+
+    /// Width of the whole image divided by its height, as it is shown,
+    /// e.g. for projection matrices.
+    fn aspect_ratio(&self) -> f32 {
+        let [w, h] = self.size();
+        w as f32 * self.pixel_aspect_ratio() / h as f32
+    }
+
     fn size_multisampled(&self, level: u8) -> [usize; 2] {
         let [w, h] = self.size();
         [w * level as usize, h * level as usize]
@@ -13,26 +26,129 @@ pub trait RenderTarget<Color: 'static> {
 
     fn draw_multisampled(&mut self, data: &[Color], level: u8)
     where
-        Color: VectorSpace + for<'a> std::iter::Sum<&'a Color>,
+        Color: VectorSpace,
         Color::Field: From<f32>,
     {
-        let [width, height] = self.size();
-        let [mwidth, mheight] = self.size_multisampled(level);
-        assert_eq!(data.len(), mwidth * mheight);
-
         let level = level as usize;
+        self.draw_supersampled(data, [level, level], ResolveFilter::Box);
+    }
 
-        let buf = iproduct!(0..height, 0..width)
-            .map(|(y, x)| {
-                iproduct!(0..level, 0..level)
-                    .map(|(dx, dy)| {
-                        &data[flatten_coord([mwidth, mheight], [x * level + dx, y * level + dy])]
-                    })
-                    .sum::<Color>()
-                    * Color::Field::from((level as f32).powi(2).recip())
-            })
-            .collect::<Vec<_>>();
+    /// Size to render at to take `[x, y]` samples per pixel along each axis.
+    fn size_supersampled(&self, [sx, sy]: [usize; 2]) -> [usize; 2] {
+        let [w, h] = self.size();
+        [w * sx, h * sy]
+    }
+
+    /// Draws a frame rendered at `size_supersampled(factor)`, combining the
+    /// samples with `filter`. Both factors must be at least 1.
+    fn draw_supersampled(&mut self, data: &[Color], [sx, sy]: [usize; 2], filter: ResolveFilter)
+    where
+        Color: VectorSpace,
+        Color::Field: From<f32>,
+    {
+        assert!(sx > 0 && sy > 0, "supersampling factors must be at least 1");
+        let size = self.size();
+        let ssize = self.size_supersampled([sx, sy]);
+        assert_eq!(data.len(), ssize[0] * ssize[1]);
+
+        let mut buf = Vec::with_capacity(size[0] * size[1]);
+        resolve(
+            size,
+            &SamplePattern::Grid([sx, sy]).offsets(),
+            filter,
+            |[x, y], i| data[flatten_coord(ssize, [x * sx + i % sx, y * sy + i / sx])].clone(),
+            &mut buf,
+        );
 
         self.draw(&buf);
     }
 }
+
+/// Keeps the last drawn frame in memory, e.g. to inspect it in tests.
+#[derive(Debug, Clone)]
+pub struct MemoryTarget<Color> {
+    width: usize,
+    height: usize,
+    buffer: Vec<Color>,
+}
+
+impl<Color> MemoryTarget<Color> {
+    pub fn new(width: usize, height: usize) -> Self {
+        MemoryTarget {
+            width,
+            height,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Also forgets the last frame.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.buffer.clear();
+    }
+
+    /// The last drawn frame, empty if nothing was drawn yet.
+    pub fn buffer(&self) -> &[Color] {
+        &self.buffer
+    }
+
+    /// Color at `[x, y]`, starting at the bottom left. `None` if it's out of
+    /// bounds or nothing was drawn yet.
+    pub fn pixel(&self, [x, y]: [usize; 2]) -> Option<&Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.buffer
+            .get(flatten_coord([self.width, self.height], [x, y]))
+    }
+
+    pub fn into_buffer(self) -> Vec<Color> {
+        self.buffer
+    }
+}
+
+impl<Color: Clone + 'static> RenderTarget<Color> for MemoryTarget<Color> {
+    fn size(&self) -> [usize; 2] {
+        [self.width, self.height]
+    }
+
+    fn draw(&mut self, data: &[Color]) {
+        assert_eq!(data.len(), self.width * self.height);
+
+        self.buffer.clear();
+        self.buffer.extend_from_slice(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supersampled_averages_samples() {
+        let mut target = MemoryTarget::new(2, 1);
+        // the left half of every row is white
+        let data = (0..8)
+            .map(|i| na::Vector4::repeat(if i % 4 < 2 { 1.0 } else { 0.0 }))
+            .collect::<Vec<_>>();
+        target.draw_supersampled(&data, [2, 2], ResolveFilter::Box);
+
+        assert_eq!(target.pixel([0, 0]), Some(&na::Vector4::repeat(1.0)));
+        assert_eq!(target.pixel([1, 0]), Some(&na::Vector4::repeat(0.0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "supersampling factors must be at least 1")]
+    fn zero_factor() {
+        let mut target = MemoryTarget::<na::Vector4<f32>>::new(2, 1);
+        target.draw_supersampled(&[], [0, 1], ResolveFilter::Box);
+    }
+
+    #[test]
+    #[should_panic(expected = "supersampling factors must be at least 1")]
+    fn zero_level() {
+        let mut target = MemoryTarget::<na::Vector4<f32>>::new(2, 1);
+        target.draw_multisampled(&[], 0);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::util::flatten_coord;
+
+// number of distinct jitter offsets before they repeat
+const JITTER_PERIOD: usize = 8;
+
+/// Temporal anti-aliasing.
+///
+/// Every frame is rendered with the projection shifted by a different
+/// sub-pixel offset (see `jitter_projection`) and accumulated into a history
+/// buffer. To limit ghosting in moving scenes, the history is clamped to the
+/// colors around each pixel in the current frame.
+pub struct TemporalAntiAliasing {
+    width: usize,
+    height: usize,
+    blend_factor: f32,
+    frame: usize,
+    history: Option<Vec<na::Vector4<f32>>>,
+}
+
+impl TemporalAntiAliasing {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            blend_factor: 0.1,
+            frame: 0,
+            history: None,
+        }
+    }
+
+    /// How much every new frame contributes to the result, from 0 to 1.
+    /// Lower values smooth edges more, but make changes slower to show up.
+    pub fn blend_factor(mut self, factor: f32) -> Self {
+        self.blend_factor = factor.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn size(&self) -> [usize; 2] {
+        [self.width, self.height]
+    }
+
+    /// Also discards the history.
+    pub fn resize(&mut self, [width, height]: [usize; 2]) {
+        self.width = width;
+        self.height = height;
+        self.reset();
+    }
+
+    /// Discards the history, e.g. when the camera jumps.
+    pub fn reset(&mut self) {
+        self.history = None;
+    }
+
+    /// Offset of the current frame in pixels, within half a pixel.
+    pub fn jitter(&self) -> na::Vector2<f32> {
+        let i = self.frame % JITTER_PERIOD + 1;
+        na::Vector2::new(halton(i, 2) - 0.5, halton(i, 3) - 0.5)
+    }
+
+    /// Shifts everything `projection` projects by the current jitter.
+    pub fn jitter_projection(&self, projection: &na::Matrix4<f32>) -> na::Matrix4<f32> {
+        // in normalized coordinates, a pixel is 2/size across
+        let jitter = self.jitter();
+        let offset = na::Vector3::new(
+            jitter.x * 2.0 / self.width as f32,
+            jitter.y * 2.0 / self.height as f32,
+            0.0,
+        );
+        na::Matrix4::new_translation(&offset) * projection
+    }
+
+    /// Accumulates a frame rendered with the current jitter and moves on to
+    /// the next one. Returns the anti-aliased frame.
+    pub fn resolve(&mut self, current: &[na::Vector4<f32>]) -> &[na::Vector4<f32>] {
+        let size = self.size();
+        assert_eq!(current.len(), self.width * self.height);
+        self.frame = self.frame.wrapping_add(1);
+
+        let history = match &mut self.history {
+            Some(history) => history,
+            history @ None => return history.insert(current.to_vec()),
+        };
+
+        for (y, x) in iproduct!(0..self.height, 0..self.width) {
+            let idx = flatten_coord(size, [x, y]);
+
+            let mut min = current[idx];
+            let mut max = current[idx];
+            let neighbors = iproduct!(
+                y.saturating_sub(1)..(y + 2).min(self.height),
+                x.saturating_sub(1)..(x + 2).min(self.width)
+            );
+            for (ny, nx) in neighbors {
+                let c = &current[flatten_coord(size, [nx, ny])];
+                min = min.inf(c);
+                max = max.sup(c);
+            }
+
+            let clamped = history[idx].sup(&min).inf(&max);
+            history[idx] = clamped.lerp(&current[idx], self.blend_factor);
+        }
+
+        history
+    }
+}
+
+// element of the Halton sequence, in [0, 1)
+fn halton(mut i: usize, base: usize) -> f32 {
+    let mut result = 0.0;
+    let mut f = 1.0;
+    while i > 0 {
+        f /= base as f32;
+        result += f * (i % base) as f32;
+        i /= base;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: f32) -> na::Vector4<f32> {
+        na::Vector4::new(v, v, v, 1.0)
+    }
+
+    fn red(frame: &[na::Vector4<f32>]) -> Vec<f32> {
+        frame.iter().map(|c| c.x).collect()
+    }
+
+    #[test]
+    fn halton_sequence() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert_eq!(halton(1, 3), 1.0 / 3.0);
+        assert_eq!(halton(4, 3), 1.0 / 3.0 + 1.0 / 9.0);
+    }
+
+    #[test]
+    fn jitter_stays_within_half_a_pixel_and_repeats() {
+        let mut taa = TemporalAntiAliasing::new(1, 1);
+        let mut jitters = Vec::new();
+        for _ in 0..2 * JITTER_PERIOD {
+            let jitter = taa.jitter();
+            assert!(jitter.x.abs() < 0.5 && jitter.y.abs() < 0.5, "{}", jitter);
+            jitters.push(jitter);
+            taa.resolve(&[gray(0.0)]);
+        }
+
+        let (first, second) = jitters.split_at(JITTER_PERIOD);
+        assert_eq!(first, second);
+        // every offset in a period is different
+        for (i, a) in first.iter().enumerate() {
+            assert!(first[i + 1..].iter().all(|b| a != b));
+        }
+    }
+
+    #[test]
+    fn jitter_projection_shifts_by_pixels() {
+        let taa = TemporalAntiAliasing::new(4, 2);
+        let jitter = taa.jitter();
+        let projected = taa.jitter_projection(&na::Matrix4::identity()) * na::Vector4::w();
+        // normalized coordinates span 2 across the screen
+        assert_eq!(projected.x * 4.0 / 2.0, jitter.x);
+        assert_eq!(projected.y * 2.0 / 2.0, jitter.y);
+    }
+
+    #[test]
+    fn first_frame_is_returned_as_is() {
+        let mut taa = TemporalAntiAliasing::new(2, 1);
+        assert_eq!(red(taa.resolve(&[gray(0.2), gray(0.8)])), [0.2, 0.8]);
+    }
+
+    #[test]
+    fn frames_are_blended() {
+        let mut taa = TemporalAntiAliasing::new(2, 1).blend_factor(0.25);
+        taa.resolve(&[gray(0.0), gray(1.0)]);
+        // the history is within the colors around each pixel, so it's kept
+        assert_eq!(red(taa.resolve(&[gray(1.0), gray(0.0)])), [0.25, 0.75]);
+    }
+
+    #[test]
+    fn history_is_clamped_to_the_neighborhood() {
+        let mut taa = TemporalAntiAliasing::new(3, 1).blend_factor(0.25);
+        taa.resolve(&[gray(1.0); 3]);
+        // the history is brighter than anything around, which would ghost
+        assert_eq!(
+            red(taa.resolve(&[gray(0.0), gray(0.5), gray(0.0)])),
+            [0.375, 0.5, 0.375]
+        );
+    }
+
+    #[test]
+    fn reset_discards_the_history() {
+        let mut taa = TemporalAntiAliasing::new(1, 1);
+        taa.resolve(&[gray(1.0)]);
+        taa.reset();
+        assert_eq!(red(taa.resolve(&[gray(0.0)])), [0.0]);
+
+        taa.resolve(&[gray(1.0)]);
+        taa.resize([2, 1]);
+        assert_eq!(taa.size(), [2, 1]);
+        assert_eq!(red(taa.resolve(&[gray(0.0); 2])), [0.0, 0.0]);
+    }
+}
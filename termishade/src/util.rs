@@ -1,5 +1,3 @@
-use crate::Interpolate3;
-
 pub fn map<T, U>(arr: [T; 3], mut f: impl FnMut(T) -> U) -> [U; 3] {
     let [a, b, c] = arr;
     [f(a), f(b), f(c)]
@@ -9,6 +7,15 @@ pub fn flatten_coord([width, _]: [usize; 2], [x, y]: [usize; 2]) -> usize {
     y * width + x
 }
 
+/// Converts a color buffer to 8-bit RGBA, flipping it so that it starts at
+/// the top like images do.
+pub fn to_rgba8(buffer: &[na::Vector4<f32>], width: usize, out: &mut Vec<u8>) {
+    out.clear();
+    for p in buffer.chunks(width).rev().flatten() {
+        out.extend(p.iter().map(|a| (a.clamp(0.0, 1.0) * 255.0) as u8));
+    }
+}
+
 pub fn to_screenspace([width, height]: [usize; 2], p: na::Vector2<f32>) -> na::Vector2<f32> {
     let mut v = p / 2.0 + na::Vector2::new(0.5, 0.5);
     v.x *= width as f32;
@@ -28,15 +35,21 @@ pub fn bounding_box(
     [width, height]: [usize; 2],
     ps: &[na::Vector2<f32>; 3],
 ) -> Option<[[usize; 2]; 2]> {
-    if ps
-        .into_iter()
-        .all(|p| p.x < 0.0 || p.x > (width - 1) as f32 || p.y < 0.0 || p.y > (height - 1) as f32)
+    let [a, b, c] = ps;
+
+    // the triangle can cover the screen with every vertex outside of it
+    let min = a.inf(b).inf(c);
+    let max = a.sup(b).sup(c);
+    if width == 0
+        || height == 0
+        || max.x < 0.0
+        || max.y < 0.0
+        || min.x > (width - 1) as f32
+        || min.y > (height - 1) as f32
     {
         return None;
     }
 
-    let [a, b, c] = ps;
-
     let sx = (a.x).min(b.x).min(c.x).floor().max(0.0) as usize;
     let sy = (a.y).min(b.y).min(c.y).floor().max(0.0) as usize;
     let ex = (a.x).max(b.x).max(c.x).ceil().min((width - 1) as f32) as usize;
@@ -45,9 +58,96 @@ pub fn bounding_box(
     Some([[sx, sy], [ex, ey]])
 }
 
+/// Whether `p` is covered by the triangle `ps`, whichever way it's wound.
+///
+/// Points exactly on an edge follow the top-left rule, with up towards
+/// positive y: only the triangle the edge is a top or left edge of covers
+/// them, so that triangles sharing an edge don't both cover its points.
 pub fn is_point_inside_triangle(ps: &[na::Vector2<f32>; 3], p: na::Vector2<f32>) -> bool {
-    na::Vector2::<f32>::to_barycentric(*ps, p)
-        .iter()
-        .sum::<f32>()
-        <= 1.01
+    let [a, b, c] = *ps;
+    // counter-clockwise, so that the inside is left of every edge
+    let [a, b, c] = match edge(a, b, c) {
+        area if area > 0.0 => [a, b, c],
+        area if area < 0.0 => [a, c, b],
+        _ => return false,
+    };
+
+    [(a, b), (b, c), (c, a)].iter().all(|&(from, to)| {
+        let e = edge(from, to, p);
+        e > 0.0 || e == 0.0 && is_top_left(to - from)
+    })
+}
+
+/// Twice the signed area of the triangle `abc`, positive if it's
+/// counter-clockwise.
+pub fn edge(a: na::Vector2<f32>, b: na::Vector2<f32>, c: na::Vector2<f32>) -> f32 {
+    (b - a).perp(&(c - a))
+}
+
+// of a counter-clockwise triangle, top edges go left and left edges go down
+fn is_top_left(direction: na::Vector2<f32>) -> bool {
+    direction.y < 0.0 || direction.y == 0.0 && direction.x < 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32) -> na::Vector2<f32> {
+        na::Vector2::new(x, y)
+    }
+
+    #[test]
+    fn top_left_rule() {
+        // bottom, diagonal and left edges
+        let triangle = [v(0.0, 0.0), v(4.0, 0.0), v(0.0, 4.0)];
+        let reversed = [v(0.0, 0.0), v(0.0, 4.0), v(4.0, 0.0)];
+
+        for ps in &[triangle, reversed] {
+            let inside = |x, y| is_point_inside_triangle(ps, v(x, y));
+            assert!(inside(1.0, 1.0));
+            assert!(inside(0.0, 2.0), "left edge");
+            assert!(!inside(2.0, 0.0), "bottom edge");
+            assert!(!inside(2.0, 2.0), "diagonal edge");
+            assert!(!inside(0.0, 0.0));
+            assert!(!inside(4.0, 0.0));
+            assert!(!inside(0.0, 4.0));
+            assert!(!inside(-1.0, 1.0));
+        }
+
+        // top edge
+        let triangle = [v(0.0, 4.0), v(2.0, 0.0), v(4.0, 4.0)];
+        assert!(is_point_inside_triangle(&triangle, v(2.0, 4.0)));
+        assert!(is_point_inside_triangle(&triangle, v(0.0, 4.0)));
+        assert!(!is_point_inside_triangle(&triangle, v(4.0, 4.0)));
+    }
+
+    #[test]
+    fn degenerate_triangles_cover_nothing() {
+        let line = [v(0.0, 0.0), v(2.0, 2.0), v(4.0, 4.0)];
+        assert!(!is_point_inside_triangle(&line, v(2.0, 2.0)));
+        let point = [v(1.0, 1.0); 3];
+        assert!(!is_point_inside_triangle(&point, v(1.0, 1.0)));
+    }
+
+    #[test]
+    fn shared_edges_and_vertices_are_covered_once() {
+        // a square split into a fan around its center, with every edge and
+        // vertex on a point of the grid
+        let corners = [v(0.0, 0.0), v(4.0, 0.0), v(4.0, 4.0), v(0.0, 4.0)];
+        let triangles = (0..4)
+            .map(|i| [v(2.0, 2.0), corners[i], corners[(i + 1) % 4]])
+            .collect::<Vec<_>>();
+
+        for (x, y) in iproduct!(0..=4, 0..=4) {
+            let p = v(x as f32, y as f32);
+            let count = triangles
+                .iter()
+                .filter(|t| is_point_inside_triangle(t, p))
+                .count();
+            // the outline of the square follows the same rule
+            let expected = (x < 4 && y > 0) as usize;
+            assert_eq!(count, expected, "covered {} times at {:?}", count, [x, y]);
+        }
+    }
 }
@@ -0,0 +1,562 @@
+//! Vertices stored as packed bytes, described by attribute layouts.
+
+use std::convert::TryInto;
+
+/// How an attribute is stored. All values are little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    F32,
+    F32x2,
+    F32x3,
+    F32x4,
+    U8x4,
+    /// Mapped from `0..=255` to `0.0..=1.0`.
+    U8x4Norm,
+    U16x2,
+    /// Mapped from `0..=65535` to `0.0..=1.0`.
+    U16x2Norm,
+}
+
+impl Format {
+    pub fn components(self) -> usize {
+        match self {
+            Format::F32 => 1,
+            Format::F32x2 | Format::U16x2 | Format::U16x2Norm => 2,
+            Format::F32x3 => 3,
+            Format::F32x4 | Format::U8x4 | Format::U8x4Norm => 4,
+        }
+    }
+
+    /// Size in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Format::F32 | Format::F32x2 | Format::F32x3 | Format::F32x4 => 4 * self.components(),
+            Format::U8x4 | Format::U8x4Norm => 4,
+            Format::U16x2 | Format::U16x2Norm => 4,
+        }
+    }
+
+    /// Reads an attribute from the start of `bytes`. Missing components are
+    /// taken from `[0, 0, 0, 1]`.
+    pub fn decode(self, bytes: &[u8]) -> [f32; 4] {
+        let mut out = [0.0, 0.0, 0.0, 1.0];
+        for (i, component) in out.iter_mut().enumerate().take(self.components()) {
+            *component = match self {
+                Format::F32 | Format::F32x2 | Format::F32x3 | Format::F32x4 => {
+                    f32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+                }
+                Format::U8x4 => bytes[i] as f32,
+                Format::U8x4Norm => bytes[i] as f32 / 255.0,
+                Format::U16x2 => u16::from_le_bytes([bytes[i * 2], bytes[i * 2 + 1]]) as f32,
+                Format::U16x2Norm => {
+                    u16::from_le_bytes([bytes[i * 2], bytes[i * 2 + 1]]) as f32 / 65535.0
+                }
+            };
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attribute {
+    pub stream: usize,
+    /// Offset in bytes from the start of a vertex in the stream.
+    pub offset: usize,
+    pub format: Format,
+}
+
+/// Describes where the attributes of a vertex are. Attributes are numbered
+/// in the order they are added.
+///
+/// ```
+/// use termishade::vertex_buffer::{Format, VertexLayout};
+///
+/// // position and color interleaved, normals in a separate stream
+/// let layout = VertexLayout::new()
+///     .interleaved(&[Format::F32x3, Format::U8x4Norm])
+///     .interleaved(&[Format::F32x3]);
+///
+/// assert_eq!(layout.strides(), &[16, 12]);
+/// assert_eq!(layout.attributes()[1].offset, 12);
+/// assert_eq!(layout.attributes()[2].stream, 1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VertexLayout {
+    strides: Vec<usize>,
+    attributes: Vec<Attribute>,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stream where vertices are `stride` bytes apart, or that every
+    /// vertex reads the same values from if it's 0. Attributes added after it
+    /// are read from this stream.
+    pub fn stream(mut self, stride: usize) -> Self {
+        self.strides.push(stride);
+        self
+    }
+
+    /// Adds an attribute to the last stream.
+    pub fn attribute(mut self, offset: usize, format: Format) -> Self {
+        assert!(
+            !self.strides.is_empty(),
+            "attribute added before any stream"
+        );
+        let stream = self.strides.len() - 1;
+        let stride = self.strides[stream];
+        assert!(
+            stride == 0 || offset + format.size() <= stride,
+            "attribute doesn't fit in the stride"
+        );
+        self.attributes.push(Attribute {
+            stream,
+            offset,
+            format,
+        });
+        self
+    }
+
+    /// Adds a stream with attributes tightly packed one after another.
+    pub fn interleaved(self, formats: &[Format]) -> Self {
+        let stride = formats.iter().map(|f| f.size()).sum();
+        let mut layout = self.stream(stride);
+        let mut offset = 0;
+        for &format in formats {
+            layout = layout.attribute(offset, format);
+            offset += format.size();
+        }
+        layout
+    }
+
+    pub fn strides(&self) -> &[usize] {
+        &self.strides
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+}
+
+pub struct VertexBuffer<'a> {
+    layout: VertexLayout,
+    streams: Vec<&'a [u8]>,
+    len: usize,
+}
+
+impl<'a> VertexBuffer<'a> {
+    /// `streams` are the bytes of each stream of the layout. The number of
+    /// vertices is limited by the shortest stream, so at least one stream
+    /// needs a stride other than 0.
+    pub fn new(layout: VertexLayout, streams: Vec<&'a [u8]>) -> Self {
+        assert_eq!(
+            layout.strides.len(),
+            streams.len(),
+            "number of streams doesn't match the layout"
+        );
+        assert!(
+            layout.strides.iter().any(|&stride| stride != 0),
+            "vertex buffers need a stream with a stride other than 0"
+        );
+        for (n, attribute) in layout.attributes.iter().enumerate() {
+            let len = streams[attribute.stream].len();
+            let end = attribute.offset + attribute.format.size();
+            assert!(
+                layout.strides[attribute.stream] != 0 || end <= len,
+                "attribute {} needs {} bytes, but stream {} has {}",
+                n,
+                end,
+                attribute.stream,
+                len
+            );
+        }
+
+        // streams with a stride of 0 repeat the same values for every vertex
+        let len = layout
+            .strides
+            .iter()
+            .zip(&streams)
+            .filter(|(&stride, _)| stride != 0)
+            .map(|(&stride, stream)| stream.len() / stride)
+            .min()
+            .unwrap_or(0);
+
+        VertexBuffer {
+            layout,
+            streams,
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    pub fn vertex(&self, index: usize) -> Vertex<'_> {
+        assert!(index < self.len, "vertex index out of bounds");
+        Vertex {
+            buffer: self,
+            index,
+        }
+    }
+
+    pub fn vertices(&self) -> impl Iterator<Item = Vertex<'_>> {
+        (0..self.len).map(move |i| self.vertex(i))
+    }
+
+    /// Decodes every vertex.
+    pub fn to_vec<V: FromVertex>(&self) -> Vec<V> {
+        self.vertices().map(V::from_vertex).collect()
+    }
+}
+
+/// A vertex in a `VertexBuffer`.
+#[derive(Clone, Copy)]
+pub struct Vertex<'a> {
+    buffer: &'a VertexBuffer<'a>,
+    index: usize,
+}
+
+impl<'a> Vertex<'a> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Raw components of the `n`th attribute.
+    pub fn components(&self, n: usize) -> [f32; 4] {
+        let attribute = self.buffer.layout.attributes[n];
+        let stride = self.buffer.layout.strides[attribute.stream];
+        let start = self.index * stride + attribute.offset;
+        attribute
+            .format
+            .decode(&self.buffer.streams[attribute.stream][start..start + attribute.format.size()])
+    }
+
+    pub fn attribute<T: FromAttribute>(&self, n: usize) -> T {
+        T::from_components(self.components(n))
+    }
+}
+
+/// Types attributes can be decoded into.
+pub trait FromAttribute {
+    fn from_components(c: [f32; 4]) -> Self;
+}
+
+impl FromAttribute for f32 {
+    fn from_components(c: [f32; 4]) -> Self {
+        c[0]
+    }
+}
+
+impl FromAttribute for [f32; 2] {
+    fn from_components(c: [f32; 4]) -> Self {
+        [c[0], c[1]]
+    }
+}
+
+impl FromAttribute for [f32; 3] {
+    fn from_components(c: [f32; 4]) -> Self {
+        [c[0], c[1], c[2]]
+    }
+}
+
+impl FromAttribute for [f32; 4] {
+    fn from_components(c: [f32; 4]) -> Self {
+        c
+    }
+}
+
+#[cfg(feature = "na-renderer")]
+impl FromAttribute for na::Vector2<f32> {
+    fn from_components(c: [f32; 4]) -> Self {
+        na::Vector2::new(c[0], c[1])
+    }
+}
+
+#[cfg(feature = "na-renderer")]
+impl FromAttribute for na::Vector3<f32> {
+    fn from_components(c: [f32; 4]) -> Self {
+        na::Vector3::new(c[0], c[1], c[2])
+    }
+}
+
+#[cfg(feature = "na-renderer")]
+impl FromAttribute for na::Vector4<f32> {
+    fn from_components(c: [f32; 4]) -> Self {
+        c.into()
+    }
+}
+
+/// Vertex types that can be read from a `VertexBuffer`.
+///
+/// ```
+/// use termishade::vertex_buffer::{self, Format, FromVertex, VertexBuffer, VertexLayout};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Vertex {
+///     pos: [f32; 2],
+///     color: [f32; 4],
+/// }
+///
+/// impl FromVertex for Vertex {
+///     fn from_vertex(v: vertex_buffer::Vertex) -> Self {
+///         Vertex {
+///             pos: v.attribute(0),
+///             color: v.attribute(1),
+///         }
+///     }
+/// }
+///
+/// let mut bytes = Vec::new();
+/// for x in &[0.5f32, -1.0] {
+///     bytes.extend_from_slice(&x.to_le_bytes());
+/// }
+/// bytes.extend_from_slice(&[255, 0, 51, 255]);
+///
+/// let layout = VertexLayout::new().interleaved(&[Format::F32x2, Format::U8x4Norm]);
+/// let buffer = VertexBuffer::new(layout, vec![&bytes]);
+/// assert_eq!(
+///     buffer.to_vec::<Vertex>(),
+///     [Vertex {
+///         pos: [0.5, -1.0],
+///         color: [1.0, 0.0, 0.2, 1.0],
+///     }]
+/// );
+/// ```
+pub trait FromVertex {
+    fn from_vertex(v: Vertex) -> Self;
+}
+
+/// Anything the renderers can take vertices from.
+pub trait VertexSource<V> {
+    fn len(&self) -> usize;
+    fn with_vertex<T>(&self, index: usize, f: impl FnOnce(&V) -> T) -> T;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<V> VertexSource<V> for [V] {
+    fn len(&self) -> usize {
+        <[V]>::len(self)
+    }
+
+    fn with_vertex<T>(&self, index: usize, f: impl FnOnce(&V) -> T) -> T {
+        f(&self[index])
+    }
+}
+
+impl<V, const N: usize> VertexSource<V> for [V; N] {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn with_vertex<T>(&self, index: usize, f: impl FnOnce(&V) -> T) -> T {
+        f(&self[index])
+    }
+}
+
+impl<V> VertexSource<V> for Vec<V> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn with_vertex<T>(&self, index: usize, f: impl FnOnce(&V) -> T) -> T {
+        f(&self[index])
+    }
+}
+
+/// Vertices are decoded as they are needed.
+impl<'a, V: FromVertex> VertexSource<V> for VertexBuffer<'a> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn with_vertex<T>(&self, index: usize, f: impl FnOnce(&V) -> T) -> T {
+        f(&V::from_vertex(self.vertex(index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn formats() {
+        let floats = f32_bytes(&[1.5, -2.0, 0.25, 8.0]);
+        assert_eq!(Format::F32.decode(&floats), [1.5, 0.0, 0.0, 1.0]);
+        assert_eq!(Format::F32x2.decode(&floats), [1.5, -2.0, 0.0, 1.0]);
+        assert_eq!(Format::F32x3.decode(&floats), [1.5, -2.0, 0.25, 1.0]);
+        assert_eq!(Format::F32x4.decode(&floats), [1.5, -2.0, 0.25, 8.0]);
+
+        let bytes = [0, 51, 255, 7];
+        assert_eq!(Format::U8x4.decode(&bytes), [0.0, 51.0, 255.0, 7.0]);
+        assert_eq!(
+            Format::U8x4Norm.decode(&bytes),
+            [0.0, 0.2, 1.0, 7.0 / 255.0]
+        );
+
+        // little-endian 0x3300 and 0xffff
+        let shorts = [0x00, 0x33, 0xff, 0xff];
+        assert_eq!(Format::U16x2.decode(&shorts), [13056.0, 65535.0, 0.0, 1.0]);
+        assert_eq!(
+            Format::U16x2Norm.decode(&shorts),
+            [13056.0 / 65535.0, 1.0, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        let formats = [
+            (Format::F32, 1, 4),
+            (Format::F32x2, 2, 8),
+            (Format::F32x3, 3, 12),
+            (Format::F32x4, 4, 16),
+            (Format::U8x4, 4, 4),
+            (Format::U8x4Norm, 4, 4),
+            (Format::U16x2, 2, 4),
+            (Format::U16x2Norm, 2, 4),
+        ];
+        for &(format, components, size) in &formats {
+            assert_eq!(format.components(), components, "{:?}", format);
+            assert_eq!(format.size(), size, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let layout = VertexLayout::new()
+            .stream(20)
+            .attribute(4, Format::F32x3)
+            .attribute(16, Format::U8x4Norm)
+            .interleaved(&[Format::U16x2, Format::F32]);
+
+        assert_eq!(layout.strides(), &[20, 8]);
+        let attributes = layout
+            .attributes()
+            .iter()
+            .map(|a| (a.stream, a.offset, a.format))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attributes,
+            [
+                (0, 4, Format::F32x3),
+                (0, 16, Format::U8x4Norm),
+                (1, 0, Format::U16x2),
+                (1, 4, Format::F32),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "attribute doesn't fit in the stride")]
+    fn attribute_past_stride() {
+        VertexLayout::new().stream(8).attribute(4, Format::F32x2);
+    }
+
+    #[test]
+    #[should_panic(expected = "attribute added before any stream")]
+    fn attribute_without_stream() {
+        VertexLayout::new().attribute(0, Format::F32);
+    }
+
+    #[test]
+    fn streams() {
+        // positions with padding, then one color for every vertex
+        let positions = f32_bytes(&[1.0, 2.0, 0.0, 3.0, 4.0, 0.0, 5.0, 6.0]);
+        let color = [255, 0, 0, 255];
+        let layout = VertexLayout::new()
+            .stream(12)
+            .attribute(0, Format::F32x2)
+            .stream(0)
+            .attribute(0, Format::U8x4Norm);
+        let buffer = VertexBuffer::new(layout, vec![&positions, &color]);
+
+        // the last vertex has no padding, which isn't enough for a stride
+        assert_eq!(buffer.len(), 2);
+        let vertices = buffer
+            .vertices()
+            .map(|v| (v.index(), v.attribute::<[f32; 2]>(0), v.attribute(1)))
+            .collect::<Vec<(usize, _, [f32; 4])>>();
+        assert_eq!(
+            vertices,
+            [
+                (0, [1.0, 2.0], [1.0, 0.0, 0.0, 1.0]),
+                (1, [3.0, 4.0], [1.0, 0.0, 0.0, 1.0]),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "number of streams doesn't match the layout")]
+    fn missing_stream() {
+        let layout = VertexLayout::new().interleaved(&[Format::F32]);
+        VertexBuffer::new(layout, Vec::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "attribute 1 needs 4 bytes, but stream 1 has 3")]
+    fn short_constant_stream() {
+        let positions = f32_bytes(&[1.0, 2.0]);
+        let layout = VertexLayout::new()
+            .interleaved(&[Format::F32x2])
+            .stream(0)
+            .attribute(0, Format::U8x4Norm);
+        VertexBuffer::new(layout, vec![&positions, &[255, 0, 0]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "vertex buffers need a stream with a stride other than 0")]
+    fn only_constant_streams() {
+        let layout = VertexLayout::new().stream(0).attribute(0, Format::F32);
+        VertexBuffer::new(layout, vec![&f32_bytes(&[1.0])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "vertex index out of bounds")]
+    fn vertex_out_of_bounds() {
+        let bytes = f32_bytes(&[1.0]);
+        let layout = VertexLayout::new().interleaved(&[Format::F32]);
+        VertexBuffer::new(layout, vec![&bytes]).vertex(1);
+    }
+
+    impl FromVertex for f32 {
+        fn from_vertex(v: Vertex) -> Self {
+            v.attribute(0)
+        }
+    }
+
+    #[test]
+    fn sources() {
+        fn collect<S: VertexSource<f32> + ?Sized>(source: &S) -> Vec<f32> {
+            (0..source.len())
+                .map(|i| source.with_vertex(i, |&v| v))
+                .collect()
+        }
+
+        let array = [1.0, 2.0, 3.0];
+        assert_eq!(collect(&array), array);
+        assert_eq!(collect(&array[..]), array);
+        assert_eq!(collect(&array.to_vec()), array);
+        assert!(VertexSource::<f32>::is_empty(&[0.0f32; 0]));
+
+        let bytes = f32_bytes(&array);
+        let layout = VertexLayout::new().interleaved(&[Format::F32]);
+        assert_eq!(collect(&VertexBuffer::new(layout, vec![&bytes])), array);
+    }
+}
@@ -0,0 +1,122 @@
+#![cfg(feature = "na-renderer")]
+
+use nalgebra::{Vector2, Vector4};
+use termishade::rasterizer::TriangleRasterizer;
+use termishade::{
+    blend, BaseRenderer, ColorDepthRenderer, DrawParams, InstancedProgram, NalgebraRenderer,
+    Program,
+};
+
+const COLUMNS: usize = 4;
+
+/// Draws a column of the screen for every instance, picked by its index and
+/// colored by its data.
+struct Bars;
+
+impl Program for Bars {
+    type VertexIn = Vector2<f32>;
+    type VertexOut = Vector4<f32>;
+    type ColorOut = Vector4<f32>;
+    type Uniform = ();
+    type Intermediate = f32;
+
+    fn vertex(&self, pos: &Vector2<f32>, _: &()) -> (Vector4<f32>, f32) {
+        self.vertex_instanced(pos, &1.0, 0, &())
+    }
+
+    fn fragment(&self, _: &Vector4<f32>, color: &f32, _: &()) -> Vector4<f32> {
+        Vector4::new(*color, 0.0, 0.0, 1.0)
+    }
+}
+
+impl InstancedProgram for Bars {
+    type Instance = f32;
+
+    fn vertex_instanced(
+        &self,
+        pos: &Vector2<f32>,
+        color: &f32,
+        index: usize,
+        _: &(),
+    ) -> (Vector4<f32>, f32) {
+        let width = 2.0 / COLUMNS as f32;
+        let x = -1.0 + (index as f32 + pos.x) * width;
+        (Vector4::new(x, pos.y, 0.5, 1.0), *color)
+    }
+}
+
+// a quad from 0 to 1 horizontally, covering the screen vertically
+fn quad() -> Vec<Vector2<f32>> {
+    [
+        [0.0, -2.0],
+        [1.0, -2.0],
+        [1.0, 2.0],
+        [0.0, -2.0],
+        [1.0, 2.0],
+        [0.0, 2.0],
+    ]
+    .iter()
+    .map(|&[x, y]| Vector2::new(x, y))
+    .collect()
+}
+
+fn renderer() -> ColorDepthRenderer {
+    let mut renderer = ColorDepthRenderer::new(COLUMNS, 2);
+    renderer.clear_color(&Vector4::zeros());
+    renderer.clear_depth(1.0);
+    renderer
+}
+
+fn params() -> DrawParams<'static, Bars, TriangleRasterizer, blend::Replace> {
+    DrawParams {
+        program: &Bars,
+        rasterizer: &TriangleRasterizer,
+        blender: &blend::Replace,
+        depth_test_enabled: true,
+    }
+}
+
+fn red(renderer: &mut ColorDepthRenderer) -> Vec<f32> {
+    renderer.color_buffer().iter().map(|c| c.x).collect()
+}
+
+#[test]
+fn instances_get_their_index_and_data() {
+    let mut renderer = renderer();
+    NalgebraRenderer::draw_instanced(&mut renderer, params(), &quad(), &[0.25, 0.5, 0.75], &());
+
+    // the last column has no instance
+    assert_eq!(
+        red(&mut renderer),
+        [0.25, 0.5, 0.75, 0.0, 0.25, 0.5, 0.75, 0.0]
+    );
+}
+
+#[test]
+fn no_instances_draw_nothing() {
+    let mut renderer = renderer();
+    let instances: &[f32] = &[];
+    NalgebraRenderer::draw_instanced(&mut renderer, params(), &quad(), instances, &());
+    assert_eq!(red(&mut renderer), [0.0; 8]);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_matches_serial() {
+    use termishade::NalgebraParRenderer;
+
+    let instances = (0..COLUMNS).map(|i| i as f32 + 1.0).collect::<Vec<_>>();
+    let mut serial = renderer();
+    NalgebraRenderer::draw_instanced(&mut serial, params(), &quad(), &instances, &());
+    let mut parallel = renderer();
+    NalgebraParRenderer::draw_instanced(&mut parallel, params(), &quad(), &instances, &());
+
+    assert_eq!(red(&mut serial), [1.0, 2.0, 3.0, 4.0, 1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(serial.color_buffer(), parallel.color_buffer());
+    assert_eq!(serial.depth_buffer(), parallel.depth_buffer());
+
+    let mut empty = renderer();
+    let instances: &[f32] = &[];
+    NalgebraParRenderer::draw_instanced(&mut empty, params(), &quad(), instances, &());
+    assert_eq!(red(&mut empty), [0.0; 8]);
+}
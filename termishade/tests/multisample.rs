@@ -0,0 +1,153 @@
+#![cfg(feature = "na-renderer")]
+
+use nalgebra::{Vector2, Vector3, Vector4};
+use termishade::rasterizer::TriangleRasterizer;
+use termishade::{
+    blend, BaseRenderer, ColorDepthRenderer, DrawParams, NalgebraRenderer, Program, Rasterizer,
+};
+
+struct White;
+
+impl Program for White {
+    type VertexIn = Vector2<f32>;
+    type VertexOut = Vector4<f32>;
+    type ColorOut = Vector4<f32>;
+    type Uniform = ();
+    type Intermediate = Vector3<f32>;
+
+    fn vertex(&self, pos: &Vector2<f32>, _: &()) -> (Vector4<f32>, Vector3<f32>) {
+        (Vector4::new(pos.x, pos.y, 0.5, 1.0), Vector3::repeat(1.0))
+    }
+
+    fn fragment(&self, _: &Vector4<f32>, _: &Vector3<f32>, _: &()) -> Vector4<f32> {
+        Vector4::repeat(1.0)
+    }
+}
+
+// only implements `rasterize`, so it gets the default `rasterize_samples`
+struct Fallback;
+
+impl Rasterizer<Vector2<f32>> for Fallback {
+    fn rasterize(&self, vertices: &[Vector2<f32>; 3], size: [usize; 2]) -> Vec<[usize; 2]> {
+        TriangleRasterizer.rasterize(vertices, size)
+    }
+}
+
+fn renderer(level: u8) -> ColorDepthRenderer {
+    let mut renderer = ColorDepthRenderer::multisampled(4, 2, level);
+    renderer.clear_color(&Vector4::zeros());
+    renderer.clear_depth(1.0);
+    renderer
+}
+
+fn params<R>(rasterizer: &R) -> DrawParams<'_, White, R, blend::Replace> {
+    DrawParams {
+        program: &White,
+        rasterizer,
+        blender: &blend::Replace,
+        depth_test_enabled: true,
+    }
+}
+
+// covers the screen left of the middle of the second column
+fn left() -> Vec<Vector2<f32>> {
+    [
+        [-2.0, -2.0],
+        [-0.5, -2.0],
+        [-0.5, 2.0],
+        [-2.0, -2.0],
+        [-0.5, 2.0],
+        [-2.0, 2.0],
+    ]
+    .iter()
+    .map(|&[x, y]| Vector2::new(x, y))
+    .collect()
+}
+
+#[test]
+fn edges_are_antialiased() {
+    let mut renderer = renderer(4);
+    NalgebraRenderer::draw(&mut renderer, params(&TriangleRasterizer), &left(), &());
+
+    let resolved = renderer.resolve();
+    for row in resolved.chunks(4) {
+        let red = row.iter().map(|c| c.x).collect::<Vec<_>>();
+        assert_eq!(red, [1.0, 0.5, 0.0, 0.0]);
+    }
+}
+
+#[test]
+fn single_sample_is_not_resolved() {
+    let mut renderer = renderer(1);
+    NalgebraRenderer::draw(&mut renderer, params(&TriangleRasterizer), &left(), &());
+
+    let color = renderer.color_buffer().to_vec();
+    assert_eq!(renderer.resolve(), &color[..]);
+    assert_eq!(color[0].x, 1.0);
+    assert_eq!(color[2].x, 0.0);
+}
+
+#[test]
+fn default_rasterize_samples_matches_triangle_rasterizer() {
+    let samples = termishade::SamplePattern::RotatedGrid(3).offsets();
+    let triangle = [
+        Vector2::new(0.3, 0.7),
+        Vector2::new(9.6, 2.2),
+        Vector2::new(4.1, 7.9),
+    ];
+
+    let mut expected = TriangleRasterizer.rasterize_samples(&triangle, [10, 8], &samples);
+    expected.sort();
+    assert!(!expected.is_empty());
+    assert_eq!(
+        Fallback.rasterize_samples(&triangle, [10, 8], &samples),
+        expected
+    );
+}
+
+#[test]
+fn shared_edges_cover_samples_once() {
+    // a square split along its diagonal, which goes through samples
+    let samples = termishade::SamplePattern::Grid([2, 2]).offsets();
+    let [a, b, c, d] = [[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0]];
+    // with the corners between samples
+    let corner = |[x, y]: [f32; 2]| Vector2::new(x - 0.25, y - 0.25);
+
+    let mut masks = std::collections::HashMap::new();
+    for &[a, b, c] in &[[a, b, c], [a, c, d]] {
+        let triangle = [corner(a), corner(b), corner(c)];
+        for (pixel, mask) in TriangleRasterizer.rasterize_samples(&triangle, [8, 8], &samples) {
+            let covered = masks.entry(pixel).or_insert(0u64);
+            assert_eq!(*covered & mask, 0, "sample covered twice in {:?}", pixel);
+            *covered |= mask;
+        }
+    }
+
+    // every sample in the square is covered exactly once
+    let covered = masks.values().map(|m| m.count_ones()).sum::<u32>();
+    assert_eq!(covered, 4 * 4 * 4);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_matches_serial() {
+    use termishade::NalgebraParRenderer;
+
+    let triangle = [
+        Vector2::new(-0.9, -0.7),
+        Vector2::new(0.8, -0.2),
+        Vector2::new(-0.1, 0.9),
+    ];
+
+    let mut serial = renderer(2);
+    NalgebraRenderer::draw(&mut serial, params(&TriangleRasterizer), &triangle[..], &());
+    let mut parallel = renderer(2);
+    NalgebraParRenderer::draw(
+        &mut parallel,
+        params(&TriangleRasterizer),
+        &triangle[..],
+        &(),
+    );
+
+    assert_eq!(serial.resolve(), parallel.resolve());
+}
@@ -0,0 +1,73 @@
+#![cfg(all(feature = "na-renderer", feature = "parallel"))]
+
+use nalgebra::{Vector3, Vector4};
+use termishade::rasterizer::TriangleRasterizer;
+use termishade::{
+    blend, BaseRenderer, ColorDepthRenderer, DrawParams, NalgebraParRenderer, NalgebraRenderer,
+    Program,
+};
+
+struct Depth;
+
+impl Program for Depth {
+    // position and color
+    type VertexIn = (Vector3<f32>, f32);
+    type VertexOut = Vector4<f32>;
+    type ColorOut = Vector4<f32>;
+    type Uniform = ();
+    type Intermediate = f32;
+
+    fn vertex(&self, (pos, color): &Self::VertexIn, _: &()) -> (Vector4<f32>, f32) {
+        (pos.push(1.0), *color)
+    }
+
+    fn fragment(&self, _: &Vector4<f32>, color: &f32, _: &()) -> Vector4<f32> {
+        Vector4::repeat(*color)
+    }
+}
+
+// many triangles covering the whole screen at different depths, so that
+// threads race for every pixel
+fn triangles() -> Vec<(Vector3<f32>, f32)> {
+    (0..64)
+        .flat_map(|i| {
+            let z = ((i * 37) % 64) as f32 / 64.0;
+            let color = i as f32;
+            vec![
+                (Vector3::new(-3.0, -3.0, z), color),
+                (Vector3::new(3.0, -3.0, z), color),
+                (Vector3::new(0.0, 3.0, z), color),
+            ]
+        })
+        .collect()
+}
+
+fn renderer() -> ColorDepthRenderer {
+    let mut renderer = ColorDepthRenderer::new(16, 16);
+    renderer.clear_color(&Vector4::zeros());
+    renderer.clear_depth(1.0);
+    renderer
+}
+
+#[test]
+fn nearest_triangle_wins() {
+    let params = || DrawParams {
+        program: &Depth,
+        rasterizer: &TriangleRasterizer,
+        blender: &blend::Replace,
+        depth_test_enabled: true,
+    };
+    let vertices = triangles();
+
+    let mut serial = renderer();
+    NalgebraRenderer::draw(&mut serial, params(), &vertices, &());
+    // the triangle at z = 0
+    assert!(serial.color_buffer().iter().all(|c| c.x == 0.0));
+
+    for _ in 0..20 {
+        let mut parallel = renderer();
+        NalgebraParRenderer::draw(&mut parallel, params(), &vertices, &());
+        assert_eq!(parallel.color_buffer(), serial.color_buffer());
+        assert_eq!(parallel.depth_buffer(), serial.depth_buffer());
+    }
+}
@@ -0,0 +1,156 @@
+#![cfg(feature = "na-renderer")]
+
+use nalgebra::{Vector2, Vector4};
+use termishade::rasterizer::TriangleRasterizer;
+use termishade::{
+    blend, BaseRenderer, ColorDepthRenderer, DrawParams, NalgebraRenderer, Primitive, Program,
+};
+
+/// Draws the edges of every triangle as lines `width` wide, in clip space.
+struct Wireframe {
+    width: f32,
+}
+
+impl Program for Wireframe {
+    type VertexIn = Vector2<f32>;
+    type VertexOut = Vector4<f32>;
+    type ColorOut = Vector4<f32>;
+    type Uniform = ();
+    type Intermediate = f32;
+
+    fn vertex(&self, pos: &Vector2<f32>, _: &()) -> (Vector4<f32>, f32) {
+        (Vector4::new(pos.x, pos.y, 0.5, 1.0), 1.0)
+    }
+
+    fn fragment(&self, _: &Vector4<f32>, color: &f32, _: &()) -> Vector4<f32> {
+        Vector4::repeat(*color)
+    }
+
+    fn primitive(&self, triangle: Primitive<Self>, _: &(), out: &mut Vec<Primitive<Self>>) {
+        for i in 0..3 {
+            let (a, i_a) = triangle[i];
+            let (b, i_b) = triangle[(i + 1) % 3];
+            let d = (b - a).xy().normalize() * self.width / 2.0;
+            let n = Vector4::new(-d.y, d.x, 0.0, 0.0);
+            let quad = [(a - n, i_a), (b - n, i_b), (b + n, i_b), (a + n, i_a)];
+            out.push([quad[0], quad[1], quad[2]]);
+            out.push([quad[0], quad[2], quad[3]]);
+        }
+    }
+}
+
+/// Drops every triangle.
+struct Cull;
+
+impl Program for Cull {
+    type VertexIn = Vector2<f32>;
+    type VertexOut = Vector4<f32>;
+    type ColorOut = Vector4<f32>;
+    type Uniform = ();
+    type Intermediate = f32;
+
+    fn vertex(&self, pos: &Vector2<f32>, _: &()) -> (Vector4<f32>, f32) {
+        Wireframe { width: 0.0 }.vertex(pos, &())
+    }
+
+    fn fragment(&self, _: &Vector4<f32>, color: &f32, _: &()) -> Vector4<f32> {
+        Vector4::repeat(*color)
+    }
+
+    fn primitive(&self, _: Primitive<Self>, _: &(), _: &mut Vec<Primitive<Self>>) {}
+}
+
+fn renderer() -> ColorDepthRenderer {
+    let mut renderer = ColorDepthRenderer::new(16, 16);
+    renderer.clear_color(&Vector4::zeros());
+    renderer.clear_depth(1.0);
+    renderer
+}
+
+fn params<P>(program: &P) -> DrawParams<'_, P, TriangleRasterizer, blend::Replace> {
+    DrawParams {
+        program,
+        rasterizer: &TriangleRasterizer,
+        blender: &blend::Replace,
+        depth_test_enabled: true,
+    }
+}
+
+fn triangle() -> [Vector2<f32>; 3] {
+    [
+        Vector2::new(-0.8, -0.8),
+        Vector2::new(0.8, -0.8),
+        Vector2::new(0.0, 0.8),
+    ]
+}
+
+fn covered(renderer: &mut ColorDepthRenderer) -> Vec<bool> {
+    renderer.color_buffer().iter().map(|c| c.x > 0.0).collect()
+}
+
+#[test]
+fn wireframe_draws_only_edges() {
+    let mut solid = renderer();
+    NalgebraRenderer::draw(
+        &mut solid,
+        params(&Wireframe { width: 0.0 }),
+        &triangle(),
+        &(),
+    );
+    // lines without width cover nothing
+    assert!(covered(&mut solid).iter().all(|&c| !c));
+
+    let mut wireframe = renderer();
+    NalgebraRenderer::draw(
+        &mut wireframe,
+        params(&Wireframe { width: 0.2 }),
+        &triangle(),
+        &(),
+    );
+    let covered = covered(&mut wireframe);
+    let at = |x: usize, y: usize| covered[y * 16 + x];
+    // on the bottom edge, on the left edge and inside the triangle
+    assert!(at(8, 2));
+    assert!(at(3, 4));
+    assert!(!at(8, 8));
+    assert!(!at(0, 0));
+}
+
+#[test]
+fn dropped_triangles_are_not_drawn() {
+    let mut renderer = renderer();
+    NalgebraRenderer::draw(&mut renderer, params(&Cull), &triangle(), &());
+    assert!(covered(&mut renderer).iter().all(|&c| !c));
+}
+
+#[test]
+#[should_panic(expected = "vertex count must be a multiple of 3, got 2")]
+fn incomplete_triangle() {
+    let mut renderer = renderer();
+    let vertices = &triangle()[..2];
+    NalgebraRenderer::draw(&mut renderer, params(&Cull), vertices, &());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_matches_serial() {
+    use termishade::NalgebraParRenderer;
+
+    let program = Wireframe { width: 0.2 };
+    let mut serial = renderer();
+    NalgebraRenderer::draw(&mut serial, params(&program), &triangle(), &());
+    let mut parallel = renderer();
+    NalgebraParRenderer::draw(&mut parallel, params(&program), &triangle(), &());
+    assert_eq!(serial.color_buffer(), parallel.color_buffer());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+#[should_panic(expected = "vertex count must be a multiple of 3, got 2")]
+fn parallel_incomplete_triangle() {
+    use termishade::NalgebraParRenderer;
+
+    let mut renderer = renderer();
+    let vertices = &triangle()[..2];
+    NalgebraParRenderer::draw(&mut renderer, params(&Cull), vertices, &());
+}
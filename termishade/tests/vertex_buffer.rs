@@ -0,0 +1,92 @@
+#![cfg(feature = "na-renderer")]
+
+use nalgebra::{Vector2, Vector3, Vector4};
+use termishade::rasterizer::TriangleRasterizer;
+use termishade::vertex_buffer::{self, Format, FromVertex, VertexBuffer, VertexLayout};
+use termishade::{blend, BaseRenderer, ColorDepthRenderer, DrawParams, NalgebraRenderer, Program};
+
+#[derive(Clone, Copy)]
+struct Vertex {
+    pos: Vector2<f32>,
+    color: Vector3<f32>,
+}
+
+impl FromVertex for Vertex {
+    fn from_vertex(v: vertex_buffer::Vertex) -> Self {
+        Vertex {
+            pos: v.attribute(0),
+            color: v.attribute(1),
+        }
+    }
+}
+
+struct ColorProgram;
+
+impl Program for ColorProgram {
+    type VertexIn = Vertex;
+    type VertexOut = Vector4<f32>;
+    type ColorOut = Vector4<f32>;
+    type Uniform = ();
+    type Intermediate = Vector3<f32>;
+
+    fn vertex(&self, v: &Vertex, _: &()) -> (Vector4<f32>, Vector3<f32>) {
+        (Vector4::new(v.pos.x, v.pos.y, 0.5, 1.0), v.color)
+    }
+
+    fn fragment(&self, _: &Vector4<f32>, color: &Vector3<f32>, _: &()) -> Vector4<f32> {
+        color.push(1.0)
+    }
+}
+
+fn draw<S>(vertices: &S) -> Vec<Vector4<f32>>
+where
+    S: termishade::VertexSource<Vertex> + ?Sized,
+{
+    let mut renderer = ColorDepthRenderer::new(8, 8);
+    renderer.clear_color(&Vector4::zeros());
+    renderer.clear_depth(1.0);
+    NalgebraRenderer::draw(
+        &mut renderer,
+        DrawParams {
+            program: &ColorProgram,
+            rasterizer: &TriangleRasterizer,
+            blender: &blend::Replace,
+            depth_test_enabled: true,
+        },
+        vertices,
+        &(),
+    );
+    renderer.color_buffer().to_vec()
+}
+
+#[test]
+fn draws_from_packed_bytes() {
+    let triangle = [
+        ([-0.9f32, -0.9], [255u8, 0, 0, 255]),
+        ([0.9, -0.6], [0, 255, 0, 255]),
+        ([-0.2, 0.9], [0, 0, 255, 255]),
+    ];
+
+    // positions as floats and colors as normalized bytes, interleaved
+    let mut bytes = Vec::new();
+    for (pos, color) in &triangle {
+        for c in pos {
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+        bytes.extend_from_slice(color);
+    }
+    let layout = VertexLayout::new().interleaved(&[Format::F32x2, Format::U8x4Norm]);
+    let buffer = VertexBuffer::new(layout, vec![&bytes]);
+
+    let vertices = triangle
+        .iter()
+        .map(|&([x, y], [r, g, b, _])| Vertex {
+            pos: Vector2::new(x, y),
+            color: Vector3::new(r, g, b).map(|c| c as f32 / 255.0),
+        })
+        .collect::<Vec<_>>();
+
+    let drawn = draw(&buffer);
+    assert!(drawn.iter().any(|c| *c != Vector4::zeros()));
+    assert_eq!(drawn, draw(&vertices));
+}
@@ -1,9 +1,11 @@
 extern crate nalgebra_glm as glm;
 use derive_interpolate::Interpolate;
-use termion_target::TermionTarget;
+use image_target::{MarkupFormat, MarkupTarget};
+use termion_target::{ColorMode, TermionTarget};
 use termishade::{
-    blend, next::Extend, rasterizer::TriangleRasterizer, BaseRenderer,
-    ColorDepthRenderer, DrawParams, Program, NalgebraRenderer
+    blend, next::Extend, rasterizer::TriangleRasterizer, vertex_buffer, BaseRenderer,
+    ColorDepthRenderer, DrawParams, Format, FromVertex, Program, NalgebraRenderer, RenderTarget,
+    VertexBuffer, VertexLayout
 };
 
 #[cfg(feature = "wasm")]
@@ -21,6 +23,16 @@ pub struct Vertex {
     pub norm: glm::Vec3,
 }
 
+impl FromVertex for Vertex {
+    fn from_vertex(v: vertex_buffer::Vertex) -> Self {
+        Vertex {
+            pos: v.attribute(0),
+            norm: v.attribute(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct Uniform {
     pub model: glm::Mat4,
     pub pv: glm::Mat4,
@@ -62,10 +74,13 @@ impl Program for CubeProgram {
 #[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct Webrender {
     pub t: f32,
-    pub model: Vec<Vertex>,
+    // positions and normals of every vertex of the model
+    vertices: Vec<u8>,
+    layout: VertexLayout,
     pub uniform: Uniform,
     renderer: ColorDepthRenderer,
     target: TermionTarget,
+    markup: MarkupTarget,
     pub original_num_vertices: usize,
 }
 
@@ -77,28 +92,43 @@ impl Webrender {
             obj::load_obj(std::io::Cursor::new(obj)).map_err(|e| ErrString::from(format!("Invalid object: {}", e)))?;
         let original_num_vertices = model.vertices.len();
 
-        let mut model = model
+        let positions = model
             .indices
             .iter()
-            .map(|&i| {
-                let v = &model.vertices[i as usize];
-                Vertex {
-                    pos: v.position.into(),
-                    norm: v.normal.into(),
-                }
-            })
-            .collect::<Vec<_>>();
-
-        let target = TermionTarget::new_without_io(width, height)
-            .reduced_palette(!rgb);
+            .map(|&i| glm::Vec3::from(model.vertices[i as usize].position));
+        let center = positions.clone().sum::<glm::Vec3>() / model.indices.len() as f32;
+        let model_size = positions
+            .map(|pos| (pos - center).magnitude())
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or_default();
+
+        let mut vertices = Vec::new();
+        for &i in &model.indices {
+            let v = &model.vertices[i as usize];
+            let pos = glm::Vec3::from(v.position) - center;
+            for c in pos.iter().chain(&v.normal) {
+                vertices.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        let layout = VertexLayout::new().interleaved(&[Format::F32x3, Format::F32x3]);
+
+        let color_mode = if rgb { ColorMode::TrueColor } else { ColorMode::Ansi256 };
+        // every cell shows two rows of pixels
+        let target = TermionTarget::new_without_io(width, height / 2)
+            .color_mode(color_mode);
+        let [width, height] = target.size();
+        let markup = MarkupTarget::new(MarkupFormat::Html, width, height)
+            .standalone(false);
         let renderer = ColorDepthRenderer::new(width, height);
 
-        Self::center_model(&mut model);
-        let model_size = Self::model_size(&model);
         let camera_pos = glm::vec3(-5.0, 3.0, -4.0).normalize() * model_size * 2.0;
 
-        let projection =
-            glm::perspective::<f32>(width as f32 / height as f32, 3.14 / 3.0, 0.1, 10.0);
+        let projection = glm::perspective::<f32>(
+            target.aspect_ratio(),
+            std::f32::consts::FRAC_PI_3,
+            0.1,
+            10.0,
+        );
         let view = glm::look_at(&camera_pos, &glm::zero(), &glm::Vec3::y());
 
         let uniform = Uniform {
@@ -109,31 +139,16 @@ impl Webrender {
 
         Ok(Webrender {
             t: 0.0,
-            model,
+            vertices,
+            layout,
             uniform,
             renderer,
             target,
+            markup,
             original_num_vertices,
         })
     }
 
-    fn center_model(model: &mut [Vertex]) {
-        let center = model.iter()
-            .map(|v| v.pos)
-            .sum::<glm::Vec3>() / model.len() as f32;
-
-        for v in model {
-            v.pos -= center;
-        }
-    }
-
-    fn model_size(model: &[Vertex]) -> f32 {
-        model.iter()
-            .map(|v| v.pos.magnitude())
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .unwrap_or_default()
-    }
-
     pub fn step(&mut self, dt: f32) {
         self.t += dt;
 
@@ -143,6 +158,25 @@ impl Webrender {
     }
 
     pub fn render(&mut self) -> String {
+        self.draw(self.uniform);
+        self.target.draw_to_string(self.renderer.color_buffer())
+    }
+
+    /// Like `render`, but returns a `<pre>` element that can be shown without
+    /// interpreting escape sequences.
+    pub fn render_html(&mut self) -> String {
+        // `pv` is made for the terminal, whose pixels have a different
+        // aspect ratio than those of the markup
+        let stretch = self.target.aspect_ratio() / self.markup.aspect_ratio();
+        let uniform = Uniform {
+            pv: glm::scaling(&glm::vec3(stretch, 1.0, 1.0)) * self.uniform.pv,
+            ..self.uniform
+        };
+        self.draw(uniform);
+        self.markup.draw_to_string(self.renderer.color_buffer())
+    }
+
+    fn draw(&mut self, uniform: Uniform) {
         self.renderer.clear_color(&glm::vec4(0.0, 0.0, 0.0, 1.0));
         self.renderer.clear_depth(1.0);
         self.renderer.draw(
@@ -152,10 +186,8 @@ impl Webrender {
                 blender: &blend::Replace,
                 depth_test_enabled: true,
             },
-            &self.model,
-            &self.uniform,
+            &VertexBuffer::new(self.layout.clone(), vec![&self.vertices]),
+            &uniform,
         );
-
-        self.target.draw_to_string(self.renderer.color_buffer())
     }
 }